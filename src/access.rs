@@ -0,0 +1,156 @@
+use std::{net::IpAddr, str::FromStr};
+
+/// What is done with a connection from an address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum IpAction {
+    Allow,
+    /// Answered with 403 before being closed.
+    Deny,
+    /// Closed without a response, as if nothing listened.
+    Drop,
+}
+
+/// Addresses a rule applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Network {
+    /// Any IPv4 or IPv6 address.
+    All,
+    /// A CIDR block: a network address and the length of its prefix.
+    Cidr(IpAddr, u8),
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(Network::All);
+        }
+        let expected = "expected an address, a CIDR block such as 10.8.0.0/24, or all";
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| expected)?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            None => max,
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or(expected)?,
+        };
+        Ok(Network::Cidr(addr, prefix))
+    }
+}
+
+impl Network {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match *self {
+            Network::All => true,
+            Network::Cidr(network, prefix) => match (network, ip.to_canonical()) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                    u32::from(network) & mask == u32::from(ip) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                    u128::from(network) & mask == u128::from(ip) & mask
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct IpRule {
+    pub action: IpAction,
+    pub network: Network,
+}
+
+/// Decides which clients may connect, by their address. Rules are tried in
+/// the order given and the first one that matches decides, so an allowlist
+/// ends with a rule for `all`. Addresses no rule matches are allowed.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Access {
+    pub rules: Vec<IpRule>,
+}
+
+impl Access {
+    pub(crate) fn check(&self, ip: IpAddr) -> IpAction {
+        self.rules
+            .iter()
+            .find(|rule| rule.network.contains(ip))
+            .map_or(IpAction::Allow, |rule| rule.action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        ("10.8.0.0/24", "10.8.0.1", true),
+        ("10.8.0.0/24", "10.8.0.255", true),
+        ("10.8.0.0/24", "10.8.1.0", false),
+        ("10.8.0.7/24", "10.8.0.1", true),
+        ("10.8.0.1", "10.8.0.1", true),
+        ("10.8.0.1", "10.8.0.2", false),
+        ("0.0.0.0/0", "192.0.2.1", true),
+        ("0.0.0.0/0", "2001:db8::1", false),
+        ("10.8.0.0/24", "::ffff:10.8.0.9", true),
+        ("2001:db8::/32", "2001:db8:1::1", true),
+        ("2001:db8::/32", "2001:db9::1", false),
+        ("::1", "::1", true),
+        ("::/0", "10.0.0.1", false),
+        ("fe80::/10", "febf::1", true),
+        ("all", "2001:db8::1", true),
+        ("all", "127.0.0.1", true),
+    )]
+    fn test_contains(network: &str, ip: &str, expected: bool) {
+        let network: Network = network.parse().unwrap();
+        assert_eq!(network.contains(ip.parse().unwrap()), expected);
+    }
+
+    #[p_test(
+        ("10.8.0.0/33"),
+        ("2001:db8::/129"),
+        ("10.8.0.0/"),
+        ("10.8.0/24"),
+        ("localhost"),
+        ("10.8.0.0/-1"),
+    )]
+    fn test_invalid(network: &str) {
+        assert!(network.parse::<Network>().is_err());
+    }
+
+    #[p_test(
+        ("10.8.0.5", IpAction::Allow),
+        ("10.8.0.66", IpAction::Deny),
+        ("192.0.2.1", IpAction::Drop),
+        ("::1", IpAction::Drop),
+    )]
+    fn test_check(ip: &str, expected: IpAction) {
+        let rule = |action, network: &str| IpRule {
+            action,
+            network: network.parse().unwrap(),
+        };
+        let access = Access {
+            rules: vec![
+                rule(IpAction::Deny, "10.8.0.64/26"),
+                rule(IpAction::Allow, "10.8.0.0/24"),
+                rule(IpAction::Drop, "all"),
+            ],
+        };
+        assert_eq!(access.check(ip.parse().unwrap()), expected);
+    }
+
+    #[test]
+    fn test_default() {
+        let access = Access::default();
+        assert_eq!(access.check("192.0.2.1".parse().unwrap()), IpAction::Allow);
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, iter,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    base64,
+    crypt::{self, constant_time_eq},
+    decode::decode_percent,
+    headers::Headers,
+    http::Request,
+    manage::destination_path,
+    resolve::{normalize, real_relative},
+    sha::{sha1, sha256},
+    visibility::rule_matches,
+};
+
+pub(crate) const DEFAULT_REALM: &str = "rup";
+/// Methods that change files, which `write` stands for in method rules.
+const WRITE_METHODS: [&str; 7] = [
+    "POST",
+    "PUT",
+    "DELETE",
+    "MKCOL",
+    "COPY",
+    "MOVE",
+    "PROPPATCH",
+];
+/// Most credentials remembered as valid. Browsers send credentials with
+/// every request, and a bcrypt hash is deliberately slow to check.
+const MAX_VERIFIED: usize = 1024;
+
+/// HTTP Basic authentication, as configured on the command line.
+///
+/// Path rules are matched like visibility rules, so `/private/` protects
+/// that directory and `*.zip` every zip file; without any, all paths are
+/// protected. Method rules name methods, or `write` for all the methods
+/// that change files; without any, all methods are protected.
+#[derive(Debug, PartialEq)]
+pub(crate) struct AuthOptions {
+    /// `user:password` pairs.
+    pub credentials: Vec<String>,
+    /// File of `user:hash` lines, as written by htpasswd.
+    pub file: Option<PathBuf>,
+    pub realm: String,
+    pub paths: Vec<String>,
+    pub methods: Vec<String>,
+}
+
+impl Default for AuthOptions {
+    fn default() -> Self {
+        AuthOptions {
+            credentials: vec![],
+            file: None,
+            realm: DEFAULT_REALM.to_string(),
+            paths: vec![],
+            methods: vec![],
+        }
+    }
+}
+
+/// How a password is stored.
+#[derive(Debug)]
+enum Secret {
+    Plain(String),
+    /// SHA-1 digest of the password, as in `{SHA}` htpasswd entries.
+    Sha1([u8; 20]),
+    /// A crypt(3) hash: bcrypt or SHA-crypt.
+    Crypt(String),
+}
+
+impl Secret {
+    fn matches(&self, password: &str) -> bool {
+        match self {
+            Secret::Plain(plain) => constant_time_eq(plain.as_bytes(), password.as_bytes()),
+            Secret::Sha1(digest) => constant_time_eq(&sha1(password.as_bytes()), digest),
+            Secret::Crypt(hash) => crypt::verify(password, hash),
+        }
+    }
+}
+
+pub(crate) struct Auth {
+    realm: String,
+    users: HashMap<String, Secret>,
+    paths: Vec<String>,
+    methods: Vec<String>,
+    /// SHA-256 digests of `Authorization` values found valid.
+    verified: Mutex<HashSet<[u8; 32]>>,
+}
+
+impl Auth {
+    /// Loads the credentials, returning `None` if authentication isn't
+    /// enabled.
+    pub(crate) fn load(options: &AuthOptions) -> Result<Option<Auth>, String> {
+        if options.credentials.is_empty() && options.file.is_none() {
+            if !options.paths.is_empty() || !options.methods.is_empty() {
+                return Err("--auth-path and --auth-methods need --auth or --auth-file".into());
+            }
+            return Ok(None);
+        }
+
+        let mut users = HashMap::new();
+        if let Some(file) = &options.file {
+            let text = fs::read_to_string(file)
+                .map_err(|e| format!("Couldn't read {}: {e}", file.display()))?;
+            let entries = parse_htpasswd(&text).map_err(|e| format!("{}: {e}", file.display()))?;
+            users.extend(entries);
+        }
+        for pair in &options.credentials {
+            let (user, password) = pair.split_once(':').ok_or("Expected user:password")?;
+            users.insert(user.to_string(), Secret::Plain(password.to_string()));
+        }
+        if users.is_empty() {
+            return Err("No credentials given".into());
+        }
+        Ok(Some(Auth {
+            realm: options.realm.clone(),
+            users,
+            paths: options.paths.clone(),
+            methods: options.methods.iter().map(|m| m.to_uppercase()).collect(),
+            verified: Mutex::new(HashSet::new()),
+        }))
+    }
+
+    pub(crate) fn realm(&self) -> &str {
+        &self.realm
+    }
+
+    /// Whether `request` for a file under `root` may be served, because it
+    /// either needs no credentials or carries valid ones.
+    pub(crate) fn permits(&self, request: &Request, root: &Path) -> bool {
+        !self.applies(request, root) || self.authenticate(&request.headers)
+    }
+
+    /// Whether the rules ask for credentials for `request`. The destination
+    /// of a MOVE or COPY counts as much as the requested path, and path rules
+    /// are matched against both the path as requested and the file it leads
+    /// to under `root`, so that a symlink can't get around them.
+    fn applies(&self, request: &Request, root: &Path) -> bool {
+        let method = request.method.as_str();
+        let protected = self.methods.is_empty()
+            || self
+                .methods
+                .iter()
+                .any(|m| m == method || (m == "WRITE" && WRITE_METHODS.contains(&method)));
+        if !protected {
+            return false;
+        }
+        if self.paths.is_empty() {
+            return true;
+        }
+        let destination = request
+            .headers
+            .get("Destination")
+            .and_then(destination_path)
+            .and_then(|path| decode_percent(path).ok());
+        iter::once(request.path.clone())
+            .chain(destination)
+            .any(|path| match normalize(&path) {
+                Ok(segments) => {
+                    self.matches_path(&segments)
+                        || real_relative(root, &path).is_some_and(|real| {
+                            let segments: Vec<&str> =
+                                real.iter().filter_map(|s| s.to_str()).collect();
+                            self.matches_path(&segments)
+                        })
+                }
+                // such paths are refused anyway, but not before asking who
+                // is trying
+                Err(_) => true,
+            })
+    }
+
+    fn matches_path(&self, segments: &[&str]) -> bool {
+        self.paths.iter().any(|rule| rule_matches(rule, segments))
+    }
+
+    fn authenticate(&self, headers: &Headers) -> bool {
+        let Some(value) = headers.get("Authorization") else {
+            return false;
+        };
+        let key = sha256(value.as_bytes());
+        if self.verified.lock().unwrap().contains(&key) {
+            return true;
+        }
+        let Some((user, password)) = basic_credentials(value) else {
+            return false;
+        };
+        let valid = self
+            .users
+            .get(&user)
+            .is_some_and(|secret| secret.matches(&password));
+        if valid {
+            let mut verified = self.verified.lock().unwrap();
+            if verified.len() >= MAX_VERIFIED {
+                verified.clear();
+            }
+            verified.insert(key);
+        }
+        valid
+    }
+}
+
+/// User and password of a `Basic` authorization (RFC 7617).
+fn basic_credentials(value: &str) -> Option<(String, String)> {
+    let (scheme, token) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64::decode(token.trim())?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Reads the `user:hash` lines of an htpasswd file. Hashes other than
+/// bcrypt, SHA-crypt and `{SHA}`, such as Apache's MD5, are refused rather
+/// than ignored, so a user can't be locked out without notice.
+fn parse_htpasswd(text: &str) -> Result<Vec<(String, Secret)>, String> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((user, hash)) = line.split_once(':') else {
+            return Err(format!("line {}: expected user:hash", i + 1));
+        };
+        let secret = match hash.strip_prefix("{SHA}") {
+            Some(digest) => base64::decode(digest)
+                .and_then(|digest| digest.try_into().ok())
+                .map(Secret::Sha1),
+            None if crypt::is_supported(hash) => Some(Secret::Crypt(hash.to_string())),
+            None => None,
+        };
+        let Some(secret) = secret else {
+            return Err(format!(
+                "line {}: unsupported hash for user '{user}', use bcrypt (htpasswd -B)",
+                i + 1
+            ));
+        };
+        entries.push((user.to_string(), secret));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    /// A root that doesn't exist, where rules only see the requested path.
+    const NO_ROOT: &str = "/nonexistent/rup-root";
+
+    const HTPASSWD: &str = "# users\n\
+        alice:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n\
+        \n\
+        carol:$5$rounds=1000$abc$Mz4DiYKTnNKbZLo/mIp3d8Y4aQBhv3uhSwxLDy55/Y8\n";
+
+    fn auth(paths: &[&str], methods: &[&str]) -> Auth {
+        let mut users: HashMap<_, _> = parse_htpasswd(HTPASSWD).unwrap().into_iter().collect();
+        users.insert("bob".to_string(), Secret::Plain("hunter2".to_string()));
+        Auth {
+            realm: DEFAULT_REALM.to_string(),
+            users,
+            paths: paths.iter().map(|s| s.to_string()).collect(),
+            methods: methods.iter().map(|s| s.to_string()).collect(),
+            verified: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn request(method: &str, path: &str, headers: &[(&str, &str)]) -> Request {
+        let mut request = Request::try_from(format!("{method} {path} HTTP/1.1")).unwrap();
+        for (name, value) in headers {
+            request.headers.append(name, value);
+        }
+        request
+    }
+
+    #[p_test(
+        ("Basic YWxpY2U6c2VjcmV0", true),
+        ("basic  YWxpY2U6c2VjcmV0 ", true),
+        ("Basic Ym9iOmh1bnRlcjI=", true),
+        ("Basic Y2Fyb2w6c2VjcmV0", true),
+        ("Basic Ym9iOndyb25n", false),
+        ("Basic not-base64", false),
+        ("Bearer YWxpY2U6c2VjcmV0", false),
+        ("", false),
+    )]
+    fn test_authenticate(authorization: &str, expected: bool) {
+        let auth = auth(&[], &[]);
+        let request = request("GET", "/", &[("Authorization", authorization)]);
+        assert_eq!(auth.permits(&request, Path::new(NO_ROOT)), expected);
+        // valid credentials are remembered
+        assert_eq!(auth.permits(&request, Path::new(NO_ROOT)), expected);
+        assert_eq!(auth.verified.lock().unwrap().len(), expected as usize);
+        assert!(!auth.permits(&self::request("GET", "/", &[]), Path::new(NO_ROOT)));
+    }
+
+    #[p_test(
+        ("GET", "/index.html", None, false),
+        ("GET", "/private", None, true),
+        ("GET", "/private/a.txt", None, true),
+        ("GET", "/public/../private/a.txt", None, true),
+        ("GET", "/docs/private/a.txt", None, false),
+        ("GET", "/backup.zip", None, true),
+        ("PUT", "/a.txt", None, false),
+        ("COPY", "/a.txt", Some("/b.txt"), false),
+        ("MOVE", "/a.txt", Some("http://localhost/private/b.txt"), true),
+        ("MOVE", "/a.txt", Some("/private%2Fb.txt"), true),
+        ("GET", "/../a.txt", None, true),
+    )]
+    fn test_paths(method: &str, path: &str, destination: Option<&str>, expected: bool) {
+        let auth = auth(&["/private/", "*.zip"], &[]);
+        let headers: Vec<_> = destination
+            .map(|d| ("Destination", d))
+            .into_iter()
+            .collect();
+        assert_eq!(
+            auth.applies(&request(method, path, &headers), Path::new(NO_ROOT)),
+            expected
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_paths() {
+        let root = std::env::temp_dir().join(format!("rup-auth-{}", std::process::id()));
+        fs::create_dir_all(root.join("private")).unwrap();
+        fs::write(root.join("private/a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(root.join("private"), root.join("shared")).unwrap();
+
+        let auth = auth(&["/private/"], &[]);
+        assert!(auth.applies(&request("GET", "/shared/a.txt", &[]), &root));
+        assert!(auth.applies(&request("PUT", "/shared/new.txt", &[]), &root));
+        let to_link = [("Destination", "/shared/b.txt")];
+        assert!(auth.applies(&request("COPY", "/a.txt", &to_link), &root));
+        assert!(!auth.applies(&request("GET", "/a.txt", &[]), &root));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[p_test(
+        ("GET", false),
+        ("HEAD", false),
+        ("PROPFIND", false),
+        ("PUT", true),
+        ("POST", true),
+        ("MOVE", true),
+        ("PROPPATCH", true),
+    )]
+    fn test_write_methods(method: &str, expected: bool) {
+        let auth = auth(&[], &["WRITE"]);
+        assert_eq!(
+            auth.applies(&request(method, "/a.txt", &[]), Path::new(NO_ROOT)),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_methods() {
+        let auth = auth(&[], &["DELETE", "PROPFIND"]);
+        assert!(auth.applies(&request("DELETE", "/a.txt", &[]), Path::new(NO_ROOT)));
+        assert!(auth.applies(&request("PROPFIND", "/", &[]), Path::new(NO_ROOT)));
+        assert!(!auth.applies(&request("GET", "/a.txt", &[]), Path::new(NO_ROOT)));
+        assert!(!auth.applies(&request("PUT", "/a.txt", &[]), Path::new(NO_ROOT)));
+    }
+
+    #[p_test(
+        ("alice:$apr1$abc$xyz", "line 1: unsupported hash for user 'alice', use bcrypt (htpasswd -B)"),
+        ("# comment\nalice", "line 2: expected user:hash"),
+        ("alice:{SHA}c2hvcnQ=", "line 1: unsupported hash for user 'alice', use bcrypt (htpasswd -B)"),
+        ("alice:plain", "line 1: unsupported hash for user 'alice', use bcrypt (htpasswd -B)"),
+    )]
+    fn test_htpasswd_invalid(text: &str, expected: &str) {
+        assert_eq!(parse_htpasswd(text).unwrap_err(), expected);
+    }
+
+    #[test]
+    fn test_load() {
+        assert!(Auth::load(&AuthOptions::default()).unwrap().is_none());
+        let options = AuthOptions {
+            methods: vec!["write".to_string()],
+            ..AuthOptions::default()
+        };
+        assert!(Auth::load(&options).is_err());
+        let options = AuthOptions {
+            credentials: vec!["bob:hunter2".to_string()],
+            methods: vec!["write".to_string()],
+            ..AuthOptions::default()
+        };
+        let auth = Auth::load(&options).unwrap().unwrap();
+        assert_eq!(auth.methods, ["WRITE"]);
+        assert_eq!(auth.realm(), DEFAULT_REALM);
+    }
+}
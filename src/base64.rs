@@ -0,0 +1,76 @@
+/// Alphabet of the standard base64 encoding (RFC 4648).
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `data` as padded base64.
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut bytes = [0; 3];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes padded base64, returning `None` if `text` isn't valid.
+pub(crate) fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    for (i, chunk) in text.chunks(4).enumerate() {
+        let last = i == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let value = ALPHABET.iter().position(|&a| a == c)?;
+            n = n << 6 | value as u32;
+        }
+        n <<= 6 * padding;
+        decoded.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+        ("user:pa ss", "dXNlcjpwYSBzcw=="),
+    )]
+    fn test_decode(data: &str, encoded: &str) {
+        assert_eq!(decode(encoded).unwrap(), data.as_bytes());
+        assert_eq!(encode(data.as_bytes()), encoded);
+    }
+
+    #[p_test(
+        ("Zg="),
+        ("Zg=a"),
+        ("Z==="),
+        ("Zg==Zm9v"),
+        ("Zm9v!A=="),
+    )]
+    fn test_invalid(text: &str) {
+        assert_eq!(decode(text), None);
+    }
+}
@@ -0,0 +1,320 @@
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+
+use crate::http::{Request, RequestError, read_line};
+use crate::timeout::is_timeout;
+
+/// Longest chunk size line, including chunk extensions, or trailer field
+/// accepted.
+const MAX_CHUNK_LINE: usize = 4 * 1024;
+/// Largest trailer section accepted.
+const MAX_TRAILER_SIZE: usize = 16 * 1024;
+
+enum State {
+    /// Bytes left of a body with `Content-Length`.
+    Length(u64),
+    /// Next is the size line of a chunk.
+    ChunkSize,
+    /// Bytes left of the current chunk.
+    ChunkData(u64),
+    /// Next is the line break ending the data of a chunk.
+    ChunkEnd,
+    Done,
+}
+
+/// Body of a request, framed by `Content-Length` or the chunked transfer
+/// coding. Reading it yields the content; reads fail with `FileTooLarge`
+/// once more than the maximum size arrives, with `InvalidData` on malformed
+/// chunks, and with `UnexpectedEof` if the client stops short.
+pub(crate) struct Body<R: BufRead, W: Write> {
+    reader: R,
+    state: State,
+    max_size: u64,
+    received: u64,
+    /// Where to send `100 Continue` before the first read, if the client is
+    /// waiting for it before sending the body.
+    interim: Option<W>,
+}
+
+impl<R: BufRead, W: Write> Body<R, W> {
+    /// Body of `request`, to be read from `reader`. The framing is checked
+    /// up front, as well as the size if it is declared by `Content-Length`.
+    /// `interim` is used to answer `Expect: 100-continue`.
+    pub(crate) fn new(
+        reader: R,
+        request: &Request,
+        max_size: u64,
+        interim: Option<W>,
+    ) -> Result<Self, RequestError> {
+        let headers = &request.headers;
+        if let Some(expect) = headers.get_joined("Expect")
+            && !expect.trim().eq_ignore_ascii_case("100-continue")
+        {
+            return Err(RequestError::ExpectationFailed);
+        }
+
+        let state = if let Some(codings) = headers.get_joined("Transfer-Encoding") {
+            if request.version == "HTTP/1.0" {
+                return Err("Transfer-Encoding in an HTTP/1.0 request"
+                    .to_string()
+                    .into());
+            }
+            if headers.contains("Content-Length") {
+                return Err("Both Transfer-Encoding and Content-Length are sent"
+                    .to_string()
+                    .into());
+            }
+            if !codings.trim().eq_ignore_ascii_case("chunked") {
+                return Err(RequestError::UnsupportedTransferCoding);
+            }
+            State::ChunkSize
+        } else if let Some(len) = headers.get_joined("Content-Length") {
+            let len =
+                content_length(&len).ok_or_else(|| format!("Invalid Content-Length: {len}"))?;
+            if len > max_size {
+                return Err(RequestError::PayloadTooLarge);
+            }
+            State::Length(len)
+        } else {
+            State::Length(0)
+        };
+
+        let interim = if matches!(state, State::Length(0)) {
+            None
+        } else {
+            interim
+        };
+        Ok(Body {
+            reader,
+            state,
+            max_size,
+            received: 0,
+            interim,
+        })
+    }
+
+    /// Whether the client waits for `100 Continue` before sending the body,
+    /// which isn't sent until the body is read.
+    pub(crate) fn awaiting_continue(&self) -> bool {
+        self.interim.is_some()
+    }
+
+    /// Reads and discards whatever is left of the body, so that the next
+    /// request can be read from the connection. Returns false if the
+    /// connection can't be reused: the body is malformed or too large, or
+    /// the client still waits for `100 Continue` and may or may not send it.
+    pub(crate) fn drain(&mut self) -> bool {
+        !self.awaiting_continue() && io::copy(self, &mut io::sink()).is_ok()
+    }
+
+    fn read_data(&mut self, buf: &mut [u8], remaining: u64) -> io::Result<usize> {
+        let len = remaining.min(buf.len() as u64) as usize;
+        let n = self.reader.read(&mut buf[..len])?;
+        if n == 0 && len > 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Ok(n)
+    }
+
+    /// Reads a line of the chunked framing, without the line break.
+    fn line(&mut self) -> io::Result<String> {
+        match read_line(&mut self.reader, MAX_CHUNK_LINE)? {
+            Some(line) if line.ends_with(b"\n") => {
+                let line = String::from_utf8_lossy(&line);
+                Ok(line.trim_end_matches(['\r', '\n']).to_string())
+            }
+            Some(_) => Err(ErrorKind::UnexpectedEof.into()),
+            None => Err(invalid("Chunk line is too long")),
+        }
+    }
+
+    fn chunk_size(&mut self) -> io::Result<u64> {
+        let line = self.line()?;
+        // chunk extensions are ignored
+        let size = line.split(';').next().unwrap().trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid("Invalid chunk size"));
+        }
+        u64::from_str_radix(size, 16).map_err(|_| invalid("Invalid chunk size"))
+    }
+
+    /// Skips the trailer section, which is discarded.
+    fn skip_trailers(&mut self) -> io::Result<()> {
+        let mut remaining = MAX_TRAILER_SIZE;
+        loop {
+            let line = self.line()?;
+            if line.is_empty() {
+                return Ok(());
+            }
+            remaining = remaining
+                .checked_sub(line.len())
+                .ok_or_else(|| invalid("Trailer section is too large"))?;
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Read for Body<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(mut interim) = self.interim.take() {
+            interim.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            interim.flush()?;
+        }
+        loop {
+            match self.state {
+                State::Length(0) | State::Done => return Ok(0),
+                State::Length(remaining) => {
+                    let n = self.read_data(buf, remaining)?;
+                    self.state = State::Length(remaining - n as u64);
+                    return Ok(n);
+                }
+                State::ChunkSize => {
+                    let size = self.chunk_size()?;
+                    if size == 0 {
+                        self.skip_trailers()?;
+                        self.state = State::Done;
+                        continue;
+                    }
+                    self.received = self.received.saturating_add(size);
+                    if self.received > self.max_size {
+                        return Err(io::Error::new(
+                            ErrorKind::FileTooLarge,
+                            "Request body is too large",
+                        ));
+                    }
+                    self.state = State::ChunkData(size);
+                }
+                State::ChunkData(remaining) => {
+                    let n = self.read_data(buf, remaining)?;
+                    self.state = match remaining - n as u64 {
+                        0 => State::ChunkEnd,
+                        remaining => State::ChunkData(remaining),
+                    };
+                    return Ok(n);
+                }
+                State::ChunkEnd => {
+                    if !self.line()?.is_empty() {
+                        return Err(invalid("Chunk data is longer than its size"));
+                    }
+                    self.state = State::ChunkSize;
+                }
+            }
+        }
+    }
+}
+
+/// The error to answer with when reading a body failed.
+pub(crate) fn body_error(e: &io::Error) -> RequestError {
+    match e.kind() {
+        ErrorKind::FileTooLarge => RequestError::PayloadTooLarge,
+        _ if is_timeout(e) => RequestError::Timeout,
+        _ => RequestError::Malformed(format!("Fail to read request body: {e}")),
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, reason)
+}
+
+/// Parses `Content-Length`, which may have been sent several times, in which
+/// case all values must be the same.
+fn content_length(value: &str) -> Option<u64> {
+    let mut values = value.split(',').map(str::trim);
+    let first = values.next()?;
+    if first.is_empty() || !first.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if values.any(|v| v != first) {
+        return None;
+    }
+    first.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    fn request(fields: &[(&str, &str)]) -> Request {
+        let mut request = Request::try_from("POST / HTTP/1.1".to_string()).unwrap();
+        for (name, value) in fields {
+            request.headers.append(name, value);
+        }
+        request
+    }
+
+    fn read_body(fields: &[(&str, &str)], input: &str, max_size: u64) -> io::Result<Vec<u8>> {
+        let mut reader = input.as_bytes();
+        let mut body = Body::<_, Vec<u8>>::new(&mut reader, &request(fields), max_size, None)
+            .map_err(|e| io::Error::other(e.reason().to_string()))?;
+        let mut out = vec![];
+        body.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[p_test(
+        (vec![], "ignored", ""),
+        (vec![("Content-Length", "5")], "hello, world", "hello"),
+        (vec![("Content-Length", "5, 5")], "hello, world", "hello"),
+        (vec![("Transfer-Encoding", "chunked")], "5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n", "hello, world"),
+        (vec![("Transfer-Encoding", "Chunked")], "a\nhello, wor\n0\nX-A: 1\n\n", "hello, wor"),
+    )]
+    fn test_body(fields: Vec<(&str, &str)>, input: &str, expected: &str) {
+        assert_eq!(read_body(&fields, input, 100).unwrap(), expected.as_bytes());
+    }
+
+    #[p_test(
+        (vec![("Content-Length", "10")], "short", ErrorKind::UnexpectedEof),
+        (vec![("Transfer-Encoding", "chunked")], "5\r\nhello\r\n", ErrorKind::UnexpectedEof),
+        (vec![("Transfer-Encoding", "chunked")], "x\r\nhello\r\n0\r\n\r\n", ErrorKind::InvalidData),
+        (vec![("Transfer-Encoding", "chunked")], "+5\r\nhello\r\n0\r\n\r\n", ErrorKind::InvalidData),
+        (vec![("Transfer-Encoding", "chunked")], "3\r\nhello\r\n0\r\n\r\n", ErrorKind::InvalidData),
+        (vec![("Transfer-Encoding", "chunked")], "40\r\n", ErrorKind::FileTooLarge),
+        (vec![("Transfer-Encoding", "chunked")], "30\r\n", ErrorKind::UnexpectedEof),
+    )]
+    fn test_body_error(fields: Vec<(&str, &str)>, input: &str, kind: ErrorKind) {
+        assert_eq!(read_body(&fields, input, 50).unwrap_err().kind(), kind);
+    }
+
+    fn new_error(fields: &[(&str, &str)]) -> Option<u16> {
+        let request = request(fields);
+        Body::<_, Vec<u8>>::new(&b""[..], &request, 10, None)
+            .err()
+            .map(|e| e.code())
+    }
+
+    #[p_test(
+        (vec![("Content-Length", "10")], None),
+        (vec![("Content-Length", "11")], Some(413)),
+        (vec![("Content-Length", "-1")], Some(400)),
+        (vec![("Content-Length", "1, 2")], Some(400)),
+        (vec![("Transfer-Encoding", "gzip, chunked")], Some(501)),
+        (vec![("Transfer-Encoding", "chunked"), ("Content-Length", "1")], Some(400)),
+        (vec![("Expect", "100-continue")], None),
+        (vec![("Expect", "something")], Some(417)),
+    )]
+    fn test_new(fields: Vec<(&str, &str)>, expected: Option<u16>) {
+        assert_eq!(new_error(&fields), expected);
+    }
+
+    #[test]
+    fn test_continue() {
+        let request = request(&[("Content-Length", "5"), ("Expect", "100-continue")]);
+        let mut interim = vec![];
+        let mut body = Body::new(&b"hello"[..], &request, 10, Some(&mut interim)).unwrap();
+        assert!(body.awaiting_continue());
+        assert!(!body.drain());
+        let mut out = vec![];
+        body.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello");
+        assert!(body.drain());
+        assert_eq!(interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn test_drain() {
+        let request = request(&[("Transfer-Encoding", "chunked")]);
+        let mut reader = &b"5\r\nhello\r\n0\r\n\r\nGET / HTTP/1.1\r\n"[..];
+        let mut body = Body::<_, Vec<u8>>::new(&mut reader, &request, 10, None).unwrap();
+        assert!(body.drain());
+        assert_eq!(reader, b"GET / HTTP/1.1\r\n");
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{self, Write};
+
+use crate::headers::Headers;
+
+/// Writes a body in the chunked transfer coding, for responses whose length
+/// isn't known when the head is sent. Every write becomes one chunk, so
+/// small writes are best buffered. Fields only known once the body has been
+/// written can follow it as trailers, announced in the `Trailer` header.
+pub(crate) struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        ChunkedWriter { inner }
+    }
+
+    /// Writes the last, empty chunk ending the body, followed by `trailers`.
+    pub(crate) fn finish(mut self, trailers: &Headers) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n")?;
+        for (name, value) in trailers.iter() {
+            write!(self.inner, "{name}: {value}\r\n")?;
+        }
+        self.inner.write_all(b"\r\n")?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // an empty chunk would end the body
+        if !buf.is_empty() {
+            write!(self.inner, "{:x}\r\n", buf.len())?;
+            self.inner.write_all(buf)?;
+            self.inner.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        let mut writer = ChunkedWriter::new(vec![]);
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(&[b'a'; 20]).unwrap();
+        let out = writer.finish(&Headers::new()).unwrap();
+        let expected = format!("5\r\nhello\r\n14\r\n{}\r\n0\r\n\r\n", "a".repeat(20));
+        assert_eq!(out, expected.as_bytes());
+    }
+
+    #[test]
+    fn test_trailers() {
+        let mut writer = ChunkedWriter::new(vec![]);
+        writer.write_all(b"body").unwrap();
+        let mut trailers = Headers::new();
+        trailers.append("Server-Timing", "total;dur=1.5");
+        trailers.append("X-A", "1");
+        let out = writer.finish(&trailers).unwrap();
+        let expected = "4\r\nbody\r\n0\r\nServer-Timing: total;dur=1.5\r\nX-A: 1\r\n\r\n";
+        assert_eq!(out, expected.as_bytes());
+    }
+}
@@ -1,9 +1,34 @@
-use std::{path::PathBuf, process::exit};
+use std::{
+    fmt::Display,
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    process::exit,
+    str::FromStr,
+};
 
 use colorust::{Color, Style};
 
+use crate::{
+    access::{Access, IpAction, IpRule},
+    auth::{AuthOptions, DEFAULT_REALM},
+    limit::Limits,
+    resolve::SymlinkPolicy,
+    throttle::{Condition, Throttle},
+    tls::TlsOptions,
+    upload::ConflictPolicy,
+    visibility::Visibility,
+};
+
 pub(crate) const VERSION: &str = env!("CARGO_PKG_VERSION");
 const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_KEEP_ALIVE_TIMEOUT: NonZeroU64 = NonZeroU64::new(5).unwrap();
+const DEFAULT_HEADER_TIMEOUT: NonZeroU64 = NonZeroU64::new(10).unwrap();
+const DEFAULT_BODY_TIMEOUT: NonZeroU64 = NonZeroU64::new(30).unwrap();
+const DEFAULT_WRITE_TIMEOUT: NonZeroU64 = NonZeroU64::new(30).unwrap();
+const DEFAULT_MAX_BODY_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_MAX_REQUESTS: NonZeroUsize = NonZeroUsize::new(100).unwrap();
+const DEFAULT_WORKERS: NonZeroUsize = NonZeroUsize::new(32).unwrap();
+const DEFAULT_QUEUE: usize = 64;
 
 fn show_version() {
     println!("rup {VERSION}");
@@ -28,6 +53,181 @@ fn show_help() {
         &format!("Port to use [default: {DEFAULT_PORT}]"),
     );
     print_opt("-r, --root", "<PATH>", "Base directory [default: \".\"]");
+    print_opt(
+        "    --symlinks",
+        "<POLICY>",
+        "Symlinks to follow: follow, inside (the root) or never [default: inside]",
+    );
+    print_opt(
+        "    --allow",
+        "<GLOB>",
+        "Serve matching paths, even dotfiles (repeatable)",
+    );
+    print_opt(
+        "    --deny",
+        "<GLOB>",
+        "Never serve or list matching paths (repeatable)",
+    );
+    print_opt(
+        "    --allow-ip",
+        "<CIDR>",
+        "Let matching clients connect (repeatable)",
+    );
+    print_opt(
+        "    --deny-ip",
+        "<CIDR>",
+        "Answer matching clients with 403 (repeatable)",
+    );
+    print_opt(
+        "    --drop-ip",
+        "<CIDR>",
+        "Close connections from matching clients at once (repeatable)",
+    );
+    print_opt(
+        "    --no-compress",
+        "",
+        "Never compress responses on the fly",
+    );
+    print_opt(
+        "    --write",
+        "",
+        "Allow uploading, deleting and moving files under the root",
+    );
+    print_opt(
+        "    --create-dirs",
+        "",
+        "Create missing parent directories of uploads",
+    );
+    print_opt(
+        "    --recursive-delete",
+        "",
+        "Allow deleting directories that aren't empty, which --webdav doesn't imply",
+    );
+    print_opt(
+        "    --webdav",
+        "",
+        "Serve WebDAV, so that the root can be mounted as a network drive",
+    );
+    print_opt(
+        "    --on-conflict",
+        "<POLICY>",
+        "Listing uploads whose name is taken: rename, overwrite or reject [default: rename]",
+    );
+    print_opt(
+        "    --tls-cert",
+        "<PATH>",
+        "Serve HTTPS with the certificate chain in this PEM file",
+    );
+    print_opt(
+        "    --tls-key",
+        "<PATH>",
+        "PEM file of the private key of the certificate",
+    );
+    print_opt(
+        "    --tls-self-signed",
+        "",
+        "Serve HTTPS with a generated certificate for this machine's names and default-route addresses",
+    );
+    print_opt(
+        "    --redirect-http",
+        "<PORT>",
+        "Also listen for plain HTTP on this port, redirecting every request to HTTPS",
+    );
+    print_opt(
+        "    --hsts",
+        "<SECS>",
+        "Tell browsers to use only HTTPS with the server for this long",
+    );
+    print_opt(
+        "    --auth",
+        "<LOGIN>",
+        "Ask for credentials given as user:password (repeatable)",
+    );
+    print_opt(
+        "    --auth-file",
+        "<PATH>",
+        "Ask for credentials listed in an htpasswd file (bcrypt, SHA-crypt or {SHA})",
+    );
+    print_opt(
+        "    --realm",
+        "<REALM>",
+        &format!("Realm shown when asking for credentials [default: {DEFAULT_REALM}]"),
+    );
+    print_opt(
+        "    --auth-path",
+        "<GLOB>",
+        "Ask for credentials only for matching paths or symlink targets (repeatable)",
+    );
+    print_opt(
+        "    --auth-methods",
+        "<LIST>",
+        "Ask for credentials only for these methods, e.g. write or PUT,DELETE",
+    );
+    print_opt(
+        "    --requests-per-sec",
+        "<N>",
+        "Requests each client may send per second, then 429 [default: unlimited]",
+    );
+    print_opt(
+        "    --bytes-per-sec",
+        "<BYTES>",
+        "Bytes each client may receive per second [default: unlimited]",
+    );
+    print_opt(
+        "    --conns-per-ip",
+        "<N>",
+        "Connections each client may open at once, then 429 [default: unlimited]",
+    );
+    print_opt(
+        "    --throttle",
+        "<COND>",
+        "Simulate a slow network: slow-3g, 3g, slow-4g, 4g or e.g. bandwidth=20000,latency=300",
+    );
+    print_opt(
+        "    --throttle-path",
+        "<RULE>",
+        "Simulate a network for paths matching GLOB=COND, e.g. '*.js=3g' (repeatable)",
+    );
+    print_opt(
+        "    --keep-alive-timeout",
+        "<SECS>",
+        &format!("Idle time before closing a connection [default: {DEFAULT_KEEP_ALIVE_TIMEOUT}]"),
+    );
+    print_opt(
+        "    --header-timeout",
+        "<SECS>",
+        &format!("Time to receive a request head [default: {DEFAULT_HEADER_TIMEOUT}]"),
+    );
+    print_opt(
+        "    --body-timeout",
+        "<SECS>",
+        &format!("Time a read of the request body may take [default: {DEFAULT_BODY_TIMEOUT}]"),
+    );
+    print_opt(
+        "    --write-timeout",
+        "<SECS>",
+        &format!("Time a blocked write may take [default: {DEFAULT_WRITE_TIMEOUT}]"),
+    );
+    print_opt(
+        "    --max-body-size",
+        "<BYTES>",
+        &format!("Largest request body accepted [default: {DEFAULT_MAX_BODY_SIZE}]"),
+    );
+    print_opt(
+        "    --max-requests",
+        "<N>",
+        &format!("Requests served per connection [default: {DEFAULT_MAX_REQUESTS}]"),
+    );
+    print_opt(
+        "    --workers",
+        "<N>",
+        &format!("Connections served at the same time [default: {DEFAULT_WORKERS}]"),
+    );
+    print_opt(
+        "    --queue",
+        "<N>",
+        &format!("Connections waiting for a worker before 503 [default: {DEFAULT_QUEUE}]"),
+    );
     print_opt("-h, --help", "", "Print help information");
     print_opt("-V, --version", "", "Print version information");
 }
@@ -36,11 +236,32 @@ fn show_help() {
 pub(crate) struct Args {
     pub port: u16,
     pub path: PathBuf,
+    pub symlinks: SymlinkPolicy,
+    pub visibility: Visibility,
+    pub access: Access,
+    pub compress: bool,
+    pub write: bool,
+    pub create_dirs: bool,
+    pub recursive_delete: bool,
+    pub webdav: bool,
+    pub on_conflict: ConflictPolicy,
+    pub tls: TlsOptions,
+    pub auth: AuthOptions,
+    pub limits: Limits,
+    pub throttle: Throttle,
+    pub keep_alive_timeout: NonZeroU64,
+    pub header_timeout: NonZeroU64,
+    pub body_timeout: NonZeroU64,
+    pub write_timeout: NonZeroU64,
+    pub max_body_size: u64,
+    pub max_requests: NonZeroUsize,
+    pub workers: NonZeroUsize,
+    pub queue: usize,
 }
 
 #[derive(Debug, PartialEq)]
 enum ParseResult {
-    Args(Args),
+    Args(Box<Args>),
     Help,
     Version,
 }
@@ -78,64 +299,239 @@ impl<'a> ArgsParser<'a> {
         &self.tokens[self.current - 1]
     }
 
+    /// Takes the value of option `name`, which must follow the option.
+    fn value(&mut self, name: &str) -> Result<String, ParseError> {
+        if self.is_at_end() || self.peek().starts_with('-') {
+            let reason = format!(
+                "{}: The argument '{}' requires a value but none was supplied",
+                "error".bright_red(),
+                name.yellow()
+            );
+            return Err(ParseError { reason });
+        }
+        Ok(self.advance().clone())
+    }
+
+    /// Takes the value of option `name` and parses it into `T`.
+    fn parsed_value<T>(&mut self, name: &str) -> Result<T, ParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.value(name)?;
+        value.parse().map_err(|e| {
+            let reason = format!(
+                "Invalid value '{}' for '{}': {}",
+                value.yellow(),
+                name.yellow(),
+                e
+            );
+            ParseError { reason }
+        })
+    }
+
     fn parse(&mut self) -> Result<ParseResult, ParseError> {
         let mut ret = Args {
             port: DEFAULT_PORT,
             path: PathBuf::from("."),
+            symlinks: SymlinkPolicy::Inside,
+            visibility: Visibility::default(),
+            access: Access::default(),
+            compress: true,
+            write: false,
+            create_dirs: false,
+            recursive_delete: false,
+            webdav: false,
+            on_conflict: ConflictPolicy::Rename,
+            tls: TlsOptions::default(),
+            auth: AuthOptions::default(),
+            limits: Limits::default(),
+            throttle: Throttle::default(),
+            keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            header_timeout: DEFAULT_HEADER_TIMEOUT,
+            body_timeout: DEFAULT_BODY_TIMEOUT,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_requests: DEFAULT_MAX_REQUESTS,
+            workers: DEFAULT_WORKERS,
+            queue: DEFAULT_QUEUE,
         };
 
         while !self.is_at_end() {
             let token = self.advance();
             match token.as_str() {
                 "-p" | "--port" => {
-                    let port_str = self.peek();
-                    if !port_str.starts_with('-') {
-                        ret.port = match port_str.parse() {
-                            Ok(port) => port,
-                            Err(e) => {
-                                let reason = format!(
-                                    "Invalid value '{}' for '{}': {}",
-                                    port_str.yellow(),
-                                    "--port <PORT>".yellow(),
-                                    e
-                                );
-                                return Err(ParseError { reason });
-                            }
-                        };
-                        self.advance();
+                    ret.port = self.parsed_value("--port <PORT>")?;
+                }
+                "-r" | "--root" => {
+                    let root = self.value("--root <PATH>")?;
+                    let path = PathBuf::from(&root);
+                    if path.exists() {
+                        ret.path = path;
                     } else {
                         let reason = format!(
-                            "{}: The argument '{}' requires a value but none was supplied",
+                            "{}: The sepcified path '{}' does't exist.",
                             "error".bright_red(),
-                            "--port <PORT>".yellow()
+                            root.yellow()
                         );
                         return Err(ParseError { reason });
                     }
                 }
-                "-r" | "--root" => {
-                    let root = self.peek();
-                    if !root.starts_with('-') {
-                        let path = PathBuf::from(root);
-                        if path.exists() {
-                            ret.path = path;
-                        } else {
+                "--symlinks" => {
+                    ret.symlinks = self.parsed_value("--symlinks <POLICY>")?;
+                }
+                "--allow" => {
+                    let glob = self.value("--allow <GLOB>")?;
+                    ret.visibility.allow.push(glob);
+                }
+                "--deny" => {
+                    let glob = self.value("--deny <GLOB>")?;
+                    ret.visibility.deny.push(glob);
+                }
+                "--allow-ip" | "--deny-ip" | "--drop-ip" => {
+                    let (action, name) = match token.as_str() {
+                        "--allow-ip" => (IpAction::Allow, "--allow-ip <CIDR>"),
+                        "--deny-ip" => (IpAction::Deny, "--deny-ip <CIDR>"),
+                        _ => (IpAction::Drop, "--drop-ip <CIDR>"),
+                    };
+                    let network = self.parsed_value(name)?;
+                    ret.access.rules.push(IpRule { action, network });
+                }
+                "--no-compress" => {
+                    ret.compress = false;
+                }
+                "--write" => {
+                    ret.write = true;
+                }
+                "--create-dirs" => {
+                    ret.create_dirs = true;
+                }
+                "--recursive-delete" => {
+                    ret.recursive_delete = true;
+                }
+                "--webdav" => {
+                    ret.webdav = true;
+                }
+                "--on-conflict" => {
+                    ret.on_conflict = self.parsed_value("--on-conflict <POLICY>")?;
+                }
+                "--tls-cert" => {
+                    ret.tls.cert = Some(PathBuf::from(self.value("--tls-cert <PATH>")?));
+                }
+                "--tls-key" => {
+                    ret.tls.key = Some(PathBuf::from(self.value("--tls-key <PATH>")?));
+                }
+                "--tls-self-signed" => {
+                    ret.tls.self_signed = true;
+                }
+                "--redirect-http" => {
+                    ret.tls.redirect_port = Some(self.parsed_value("--redirect-http <PORT>")?);
+                }
+                "--hsts" => {
+                    ret.tls.hsts = Some(self.parsed_value("--hsts <SECS>")?);
+                }
+                "--auth" => {
+                    let credentials = self.value("--auth <LOGIN>")?;
+                    if credentials
+                        .split_once(':')
+                        .is_none_or(|(user, _)| user.is_empty())
+                    {
+                        let reason = format!(
+                            "Invalid value '{}' for '{}': expected user:password",
+                            credentials.yellow(),
+                            "--auth <LOGIN>".yellow()
+                        );
+                        return Err(ParseError { reason });
+                    }
+                    ret.auth.credentials.push(credentials);
+                }
+                "--auth-file" => {
+                    ret.auth.file = Some(PathBuf::from(self.value("--auth-file <PATH>")?));
+                }
+                "--realm" => {
+                    let realm = self.value("--realm <REALM>")?;
+                    if realm.contains(|c: char| c == '"' || c == '\\' || c.is_control()) {
+                        let reason = format!(
+                            "Invalid value '{}' for '{}': quotes, backslashes and control characters aren't allowed",
+                            realm.yellow(),
+                            "--realm <REALM>".yellow()
+                        );
+                        return Err(ParseError { reason });
+                    }
+                    ret.auth.realm = realm;
+                }
+                "--auth-path" => {
+                    let glob = self.value("--auth-path <GLOB>")?;
+                    ret.auth.paths.push(glob);
+                }
+                "--auth-methods" => {
+                    let methods = self.value("--auth-methods <LIST>")?;
+                    ret.auth.methods.extend(
+                        methods
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|m| !m.is_empty())
+                            .map(str::to_uppercase),
+                    );
+                }
+                "--requests-per-sec" => {
+                    ret.limits.requests = Some(self.parsed_value("--requests-per-sec <N>")?);
+                }
+                "--bytes-per-sec" => {
+                    ret.limits.bytes = Some(self.parsed_value("--bytes-per-sec <BYTES>")?);
+                }
+                "--conns-per-ip" => {
+                    ret.limits.connections = Some(self.parsed_value("--conns-per-ip <N>")?);
+                }
+                "--throttle" => {
+                    ret.throttle.default = Some(self.parsed_value("--throttle <COND>")?);
+                }
+                "--throttle-path" => {
+                    let name = "--throttle-path <RULE>";
+                    let rule = self.value(name)?;
+                    let parsed = rule
+                        .split_once('=')
+                        .ok_or_else(|| "expected GLOB=COND".to_string())
+                        .and_then(|(glob, condition)| {
+                            Ok((glob.to_string(), condition.parse::<Condition>()?))
+                        });
+                    match parsed {
+                        Ok(rule) => ret.throttle.rules.push(rule),
+                        Err(e) => {
                             let reason = format!(
-                                "{}: The sepcified path '{}' does't exist.",
-                                "error".bright_red(),
-                                root.yellow()
+                                "Invalid value '{}' for '{}': {}",
+                                rule.yellow(),
+                                name.yellow(),
+                                e
                             );
                             return Err(ParseError { reason });
                         }
-                        self.advance();
-                    } else {
-                        let reason = format!(
-                            "{}: The argument '{}' requires a value but none was supplied",
-                            "error".bright_red(),
-                            "--root <PATH>".yellow()
-                        );
-                        return Err(ParseError { reason });
                     }
                 }
+                "--keep-alive-timeout" => {
+                    ret.keep_alive_timeout = self.parsed_value("--keep-alive-timeout <SECS>")?;
+                }
+                "--header-timeout" => {
+                    ret.header_timeout = self.parsed_value("--header-timeout <SECS>")?;
+                }
+                "--body-timeout" => {
+                    ret.body_timeout = self.parsed_value("--body-timeout <SECS>")?;
+                }
+                "--write-timeout" => {
+                    ret.write_timeout = self.parsed_value("--write-timeout <SECS>")?;
+                }
+                "--max-body-size" => {
+                    ret.max_body_size = self.parsed_value("--max-body-size <BYTES>")?;
+                }
+                "--max-requests" => {
+                    ret.max_requests = self.parsed_value("--max-requests <N>")?;
+                }
+                "--workers" => {
+                    ret.workers = self.parsed_value("--workers <N>")?;
+                }
+                "--queue" => {
+                    ret.queue = self.parsed_value("--queue <N>")?;
+                }
                 "-V" | "--version" => {
                     return Ok(ParseResult::Version);
                 }
@@ -152,7 +548,7 @@ impl<'a> ArgsParser<'a> {
                 }
             }
         }
-        Ok(ParseResult::Args(ret))
+        Ok(ParseResult::Args(Box::new(ret)))
     }
 }
 
@@ -162,7 +558,7 @@ impl Args {
 
         match arg_parser.parse() {
             Ok(r) => match r {
-                ParseResult::Args(a) => a,
+                ParseResult::Args(a) => *a,
                 ParseResult::Help => {
                     show_help();
                     exit(0);
@@ -194,6 +590,251 @@ fn test_port() {
     assert_eq!(args.port, 1024);
 }
 
+#[test]
+fn test_keep_alive() {
+    let args: Vec<String> = ["--keep-alive-timeout", "10", "--max-requests", "5"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.keep_alive_timeout.get(), 10);
+        assert_eq!(args.max_requests.get(), 5);
+    } else {
+        panic!("failed to parse keep-alive options");
+    }
+}
+
+#[test]
+fn test_symlinks() {
+    let args = vec!["--symlinks".to_string(), "never".to_string()];
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.symlinks, SymlinkPolicy::Never);
+    } else {
+        panic!("failed to parse symlink policy");
+    }
+    let args = vec!["--symlinks".to_string(), "sometimes".to_string()];
+    assert!(ArgsParser::new(&args).parse().is_err());
+}
+
+#[test]
+fn test_allow_deny() {
+    let args: Vec<String> = [
+        "--allow",
+        ".well-known/",
+        "--deny",
+        ".git/",
+        "--deny",
+        "*.key",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.visibility.allow, vec![".well-known/"]);
+        assert_eq!(args.visibility.deny, vec![".git/", "*.key"]);
+    } else {
+        panic!("failed to parse allow/deny rules");
+    }
+}
+
+#[test]
+fn test_body_limits() {
+    let args: Vec<String> = ["--max-body-size", "1024", "--body-timeout", "5"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.max_body_size, 1024);
+        assert_eq!(args.body_timeout.get(), 5);
+    } else {
+        panic!("failed to parse body options");
+    }
+}
+
+#[test]
+fn test_no_compress() {
+    let args = Args::parse(&["rup".to_string()]);
+    assert!(args.compress);
+    let args = Args::parse(&["rup".to_string(), "--no-compress".to_string()]);
+    assert!(!args.compress);
+}
+
+#[test]
+fn test_write() {
+    let args = Args::parse(&["rup".to_string()]);
+    assert!(!args.write);
+    assert!(!args.create_dirs);
+    assert!(!args.recursive_delete);
+    assert!(!args.webdav);
+    let args = Args::parse(&[
+        "rup".to_string(),
+        "--write".to_string(),
+        "--create-dirs".to_string(),
+        "--recursive-delete".to_string(),
+        "--webdav".to_string(),
+    ]);
+    assert!(args.webdav);
+    assert!(args.write);
+    assert!(args.create_dirs);
+    assert!(args.recursive_delete);
+}
+
+#[test]
+fn test_ip_rules() {
+    let args: Vec<String> = [
+        "--allow-ip",
+        "10.8.0.0/24",
+        "--deny-ip",
+        "::1",
+        "--drop-ip",
+        "all",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        let actions: Vec<IpAction> = args.access.rules.iter().map(|r| r.action).collect();
+        assert_eq!(actions, [IpAction::Allow, IpAction::Deny, IpAction::Drop]);
+    } else {
+        panic!("failed to parse ip rules");
+    }
+    let args = vec!["--allow-ip".to_string(), "10.8.0.0/40".to_string()];
+    assert!(ArgsParser::new(&args).parse().is_err());
+}
+
+#[test]
+fn test_limits() {
+    let args = Args::parse(&["rup".to_string()]);
+    assert_eq!(args.limits, Limits::default());
+    let args: Vec<String> = [
+        "--requests-per-sec",
+        "20",
+        "--bytes-per-sec",
+        "1000000",
+        "--conns-per-ip",
+        "4",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.limits.requests.map(|n| n.get()), Some(20));
+        assert_eq!(args.limits.bytes.map(|n| n.get()), Some(1_000_000));
+        assert_eq!(args.limits.connections.map(|n| n.get()), Some(4));
+    } else {
+        panic!("failed to parse limits");
+    }
+    let args = vec!["--conns-per-ip".to_string(), "0".to_string()];
+    assert!(ArgsParser::new(&args).parse().is_err());
+}
+
+#[test]
+fn test_throttle() {
+    let args: Vec<String> = [
+        "--throttle",
+        "slow-4g",
+        "--throttle-path",
+        "*.js=3g,latency=100",
+        "--throttle-path",
+        "/api/=none",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.throttle.default, "slow-4g".parse().ok());
+        let globs: Vec<&str> = args.throttle.rules.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(globs, ["*.js", "/api/"]);
+        assert_eq!(args.throttle.rules[0].1, "3g,latency=100".parse().unwrap());
+    } else {
+        panic!("failed to parse throttle options");
+    }
+    for invalid in [["--throttle", "5g"], ["--throttle-path", "*.js"]] {
+        let args: Vec<String> = invalid.iter().map(|s| s.to_string()).collect();
+        assert!(ArgsParser::new(&args).parse().is_err());
+    }
+}
+
+#[test]
+fn test_on_conflict() {
+    let args = Args::parse(&["rup".to_string()]);
+    assert_eq!(args.on_conflict, ConflictPolicy::Rename);
+    let args = vec!["--on-conflict".to_string(), "reject".to_string()];
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.on_conflict, ConflictPolicy::Reject);
+    } else {
+        panic!("failed to parse conflict policy");
+    }
+    let args = vec!["--on-conflict".to_string(), "skip".to_string()];
+    assert!(ArgsParser::new(&args).parse().is_err());
+}
+
+#[test]
+fn test_tls() {
+    let args: Vec<String> = ["--tls-cert", "cert.pem", "--tls-key", "key.pem"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.tls.cert, Some(PathBuf::from("cert.pem")));
+        assert_eq!(args.tls.key, Some(PathBuf::from("key.pem")));
+        assert!(!args.tls.self_signed);
+    } else {
+        panic!("failed to parse tls options");
+    }
+    let args: Vec<String> = [
+        "--tls-self-signed",
+        "--redirect-http",
+        "8080",
+        "--hsts",
+        "3600",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert!(args.tls.self_signed);
+        assert_eq!(args.tls.redirect_port, Some(8080));
+        assert_eq!(args.tls.hsts, Some(3600));
+    } else {
+        panic!("failed to parse --tls-self-signed");
+    }
+    let args = vec!["--tls-cert".to_string()];
+    assert!(ArgsParser::new(&args).parse().is_err());
+}
+
+#[test]
+fn test_auth() {
+    let args: Vec<String> = [
+        "--auth",
+        "alice:se:cret",
+        "--auth-file",
+        ".htpasswd",
+        "--realm",
+        "Files",
+        "--auth-path",
+        "/private/",
+        "--auth-methods",
+        "write, propfind",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    if let Ok(ParseResult::Args(args)) = ArgsParser::new(&args).parse() {
+        assert_eq!(args.auth.credentials, ["alice:se:cret"]);
+        assert_eq!(args.auth.file, Some(PathBuf::from(".htpasswd")));
+        assert_eq!(args.auth.realm, "Files");
+        assert_eq!(args.auth.paths, ["/private/"]);
+        assert_eq!(args.auth.methods, ["WRITE", "PROPFIND"]);
+    } else {
+        panic!("failed to parse auth options");
+    }
+    for invalid in [["--auth", "alice"], ["--auth", ":x"], ["--realm", "a\"b"]] {
+        let args: Vec<String> = invalid.iter().map(|s| s.to_string()).collect();
+        assert!(ArgsParser::new(&args).parse().is_err());
+    }
+}
+
 #[test]
 fn test_version() {
     let args = vec!["--version".to_string(), "-p".to_string()];
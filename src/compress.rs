@@ -0,0 +1,269 @@
+use std::io::{self, Write};
+
+use crate::deflate::Deflater;
+
+/// Content codings the server can compress responses with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Coding {
+    Gzip,
+    /// The zlib format (RFC 1950), which is what HTTP calls `deflate`.
+    Deflate,
+}
+
+impl Coding {
+    /// Name used in `Accept-Encoding` and `Content-Encoding`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Coding::Gzip => "gzip",
+            Coding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Picks the coding the client prefers from an `Accept-Encoding` value,
+/// favouring gzip when both are equally acceptable.
+pub(crate) fn negotiate(accept_encoding: &str) -> Option<Coding> {
+    match preferred(accept_encoding, &["gzip", "deflate"])? {
+        "gzip" => Some(Coding::Gzip),
+        _ => Some(Coding::Deflate),
+    }
+}
+
+/// Picks the one of `codings` the client prefers from an `Accept-Encoding`
+/// value, the earlier one when several are equally acceptable. Codings not
+/// listed take the weight of `*`, if present, and are not acceptable
+/// otherwise.
+pub(crate) fn preferred<'a>(accept_encoding: &str, codings: &[&'a str]) -> Option<&'a str> {
+    let mut any = None;
+    let mut weights = vec![None; codings.len()];
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap().trim();
+        let weight = params.find_map(parse_qvalue).unwrap_or(1000);
+        let coding = if coding.eq_ignore_ascii_case("x-gzip") {
+            "gzip"
+        } else {
+            coding
+        };
+        if coding == "*" {
+            any = Some(weight);
+        } else if let Some(i) = codings.iter().position(|c| c.eq_ignore_ascii_case(coding)) {
+            weights[i] = Some(weight);
+        }
+    }
+
+    let mut best = None;
+    let mut best_weight = 0;
+    for (coding, weight) in codings.iter().zip(weights) {
+        let weight = weight.or(any).unwrap_or(0);
+        if weight > best_weight {
+            (best, best_weight) = (Some(*coding), weight);
+        }
+    }
+    best
+}
+
+/// Weight of a `q=` parameter in thousandths.
+fn parse_qvalue(param: &str) -> Option<u16> {
+    let (name, value) = param.split_once('=')?;
+    if !name.trim().eq_ignore_ascii_case("q") {
+        return None;
+    }
+    let value = value.trim();
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = format!("{frac:0<3}").parse::<u16>().ok()?;
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+/// Compresses everything written to it in the gzip or zlib format.
+pub(crate) struct Encoder<W: Write> {
+    deflater: Deflater<W>,
+    coding: Coding,
+    crc: u32,
+    adler: Adler32,
+    size: u32,
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(mut inner: W, coding: Coding) -> io::Result<Self> {
+        match coding {
+            // no file name or modification time, unknown OS
+            Coding::Gzip => inner.write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255])?,
+            // 32 KiB window, default compression level
+            Coding::Deflate => inner.write_all(&[0x78, 0x9c])?,
+        }
+        Ok(Encoder {
+            deflater: Deflater::new(inner),
+            coding,
+            crc: 0,
+            adler: Adler32::default(),
+            size: 0,
+        })
+    }
+
+    /// Writes the end of the compressed data and returns the inner writer.
+    pub(crate) fn finish(self) -> io::Result<W> {
+        let mut inner = self.deflater.finish()?;
+        match self.coding {
+            Coding::Gzip => {
+                inner.write_all(&self.crc.to_le_bytes())?;
+                inner.write_all(&self.size.to_le_bytes())?;
+            }
+            Coding::Deflate => inner.write_all(&self.adler.value().to_be_bytes())?,
+        }
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.deflater.write(buf)?;
+        match self.coding {
+            Coding::Gzip => {
+                self.crc = crc32(self.crc, &buf[..len]);
+                self.size = self.size.wrapping_add(len as u32);
+            }
+            Coding::Deflate => self.adler.update(&buf[..len]),
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deflater.flush()
+    }
+}
+
+/// Continues the CRC-32 (as used by gzip) of the data before with `data`.
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Adler32 { a: 1, b: 0 }
+    }
+}
+
+impl Adler32 {
+    const MOD: u32 = 65521;
+
+    fn update(&mut self, data: &[u8]) {
+        // sums can't overflow within chunks of this size
+        for chunk in data.chunks(5552) {
+            for &b in chunk {
+                self.a += b as u32;
+                self.b += self.a;
+            }
+            self.a %= Self::MOD;
+            self.b %= Self::MOD;
+        }
+    }
+
+    fn value(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::deflate::test::inflate;
+    use p_test::p_test;
+
+    #[p_test(
+        ("gzip, deflate, br", Some(Coding::Gzip)),
+        ("deflate", Some(Coding::Deflate)),
+        ("gzip;q=0.5, deflate", Some(Coding::Deflate)),
+        ("GZIP", Some(Coding::Gzip)),
+        ("x-gzip", Some(Coding::Gzip)),
+        ("*", Some(Coding::Gzip)),
+        ("*;q=0.5, gzip;q=0.2", Some(Coding::Deflate)),
+        ("gzip;q=0, deflate;q=0", None),
+        ("gzip;q=0.000", None),
+        ("br", None),
+        ("identity", None),
+        ("", None),
+        ("*, gzip;q=0, deflate;q=0", None),
+        ("gzip;q=2, deflate;q=0.1", Some(Coding::Gzip)),
+    )]
+    fn test_negotiate(accept_encoding: &str, expected: Option<Coding>) {
+        assert_eq!(negotiate(accept_encoding), expected);
+    }
+
+    #[p_test(
+        ("gzip, br, zstd", Some("br")),
+        ("gzip, br;q=0.5", Some("gzip")),
+        ("gzip;q=0.5, zstd;q=0.5", Some("zstd")),
+        ("*;q=0.1, br;q=0", Some("zstd")),
+        ("deflate", None),
+    )]
+    fn test_preferred(accept_encoding: &str, expected: Option<&str>) {
+        assert_eq!(
+            preferred(accept_encoding, &["br", "zstd", "gzip"]),
+            expected
+        );
+    }
+
+    #[p_test(
+        ("", 0),
+        ("a", 0xe8b7_be43),
+        ("123456789", 0xcbf4_3926),
+    )]
+    fn test_crc32(data: &str, expected: u32) {
+        assert_eq!(crc32(0, data.as_bytes()), expected);
+    }
+
+    #[test]
+    fn test_adler32() {
+        let mut adler = Adler32::default();
+        adler.update(b"Wikipedia");
+        assert_eq!(adler.value(), 0x11e6_0398);
+    }
+
+    fn encode(coding: Coding, data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(vec![], coding).unwrap();
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_gzip() {
+        let data = b"hello gzip, hello gzip, hello gzip".repeat(10);
+        let out = encode(Coding::Gzip, &data);
+        assert_eq!(out[..3], [0x1f, 0x8b, 8]);
+        let trailer = &out[out.len() - 8..];
+        assert_eq!(trailer[..4], crc32(0, &data).to_le_bytes());
+        assert_eq!(trailer[4..], (data.len() as u32).to_le_bytes());
+        assert_eq!(inflate(&out[10..out.len() - 8]), data);
+    }
+
+    #[test]
+    fn test_zlib() {
+        let data = b"hello zlib".repeat(10);
+        let out = encode(Coding::Deflate, &data);
+        assert_eq!((out[0] as u16 * 256 + out[1] as u16) % 31, 0);
+        let mut adler = Adler32::default();
+        adler.update(&data);
+        assert_eq!(out[out.len() - 4..], adler.value().to_be_bytes());
+        assert_eq!(inflate(&out[2..out.len() - 4]), data);
+    }
+}
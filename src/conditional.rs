@@ -0,0 +1,310 @@
+use std::fs::Metadata;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::date::{http_date, parse_http_date};
+use crate::headers::Headers;
+
+/// Validators of a representation, sent as `ETag` and `Last-Modified`.
+pub(crate) struct Validators {
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Strong validators of a file, derived from its modification time and
+    /// size.
+    pub(crate) fn from_metadata(md: &Metadata) -> Self {
+        let last_modified = md.modified().ok();
+        let mtime = last_modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Validators {
+            etag: format!(
+                "\"{:x}.{:x}-{:x}\"",
+                mtime.as_secs(),
+                mtime.subsec_nanos(),
+                md.len()
+            ),
+            last_modified,
+        }
+    }
+
+    /// Weak validators of a directory listing, derived from the modification
+    /// time of the directory, which changes whenever an entry is added,
+    /// removed or renamed. Listings are streamed, so they can't be derived
+    /// from the content.
+    pub(crate) fn listing(md: &Metadata) -> Self {
+        let last_modified = md.modified().ok();
+        let mtime = last_modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Validators {
+            etag: format!("W/\"{:x}.{:x}\"", mtime.as_secs(), mtime.subsec_nanos()),
+            last_modified,
+        }
+    }
+
+    /// Validators of the representation compressed with content coding
+    /// `coding`, whose entity tag must differ from the uncompressed one.
+    pub(crate) fn encoded(mut self, coding: &str) -> Self {
+        self.etag
+            .insert_str(self.etag.len() - 1, &format!("-{coding}"));
+        self
+    }
+
+    /// Adds `ETag` and `Last-Modified` fields to response headers.
+    pub(crate) fn append_to(&self, headers: &mut Headers) {
+        headers.append("ETag", &self.etag);
+        if let Some(time) = self.last_modified {
+            headers.append("Last-Modified", &http_date(time));
+        }
+    }
+
+    fn modified_after(&self, time: SystemTime) -> bool {
+        self.last_modified
+            .is_some_and(|last_modified| secs(last_modified) > secs(time))
+    }
+}
+
+/// Outcome of evaluating the preconditions of a GET or HEAD request.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Precondition {
+    Proceed,
+    /// 304 Not Modified must be sent.
+    NotModified,
+    /// 412 Precondition Failed must be sent.
+    Failed,
+}
+
+/// Evaluates the conditional request header fields of a GET or HEAD request
+/// in the order defined by RFC 9110 section 13.2.2. `If-Range` is left to
+/// `if_range`, as it only decides whether `Range` is honoured.
+pub(crate) fn evaluate(headers: &Headers, validators: &Validators) -> Precondition {
+    if let Some(tags) = headers.get_joined("If-Match") {
+        if !matches(&tags, &validators.etag, true) {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = headers.get("If-Unmodified-Since").and_then(parse_http_date)
+        && validators.modified_after(since)
+    {
+        return Precondition::Failed;
+    }
+
+    if let Some(tags) = headers.get_joined("If-None-Match") {
+        if matches(&tags, &validators.etag, false) {
+            return Precondition::NotModified;
+        }
+    } else if let Some(since) = headers.get("If-Modified-Since").and_then(parse_http_date)
+        && validators.last_modified.is_some()
+        && !validators.modified_after(since)
+    {
+        return Precondition::NotModified;
+    }
+
+    Precondition::Proceed
+}
+
+/// Evaluates the conditional request header fields of a request that writes
+/// the target, which doesn't exist yet if `validators` is `None`. Unlike for
+/// GET, a matching `If-None-Match` fails the request, so that
+/// `If-None-Match: *` prevents overwriting, and `If-Match: *` requires it.
+pub(crate) fn evaluate_write(headers: &Headers, validators: Option<&Validators>) -> Precondition {
+    if let Some(tags) = headers.get_joined("If-Match") {
+        if !validators.is_some_and(|v| matches(&tags, &v.etag, true)) {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = headers.get("If-Unmodified-Since").and_then(parse_http_date)
+        && validators.is_some_and(|v| v.modified_after(since))
+    {
+        return Precondition::Failed;
+    }
+
+    if let Some(tags) = headers.get_joined("If-None-Match")
+        && validators.is_some_and(|v| matches(&tags, &v.etag, false))
+    {
+        return Precondition::Failed;
+    }
+
+    Precondition::Proceed
+}
+
+/// Whether the `If-Range` condition holds, i.e. the representation the client
+/// has a part of is still current. Entity tags are compared strongly, dates
+/// must be an exact match of `Last-Modified`.
+pub(crate) fn if_range(value: &str, validators: &Validators) -> bool {
+    let value = value.trim();
+    if value.starts_with('"') || value.starts_with("W/") {
+        strong_eq(value, &validators.etag)
+    } else {
+        match (parse_http_date(value), validators.last_modified) {
+            (Some(date), Some(last_modified)) => secs(date) == secs(last_modified),
+            _ => false,
+        }
+    }
+}
+
+/// Checks `etag` against an entity-tag list or `*`.
+fn matches(tags: &str, etag: &str, strong: bool) -> bool {
+    if tags.trim() == "*" {
+        return true;
+    }
+    entity_tags(tags).into_iter().any(|tag| {
+        if strong {
+            strong_eq(tag, etag)
+        } else {
+            opaque(tag) == opaque(etag)
+        }
+    })
+}
+
+/// Strong comparison: both tags must be strong and identical.
+fn strong_eq(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && a == b
+}
+
+fn opaque(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+/// Splits an entity-tag list at commas outside of quoted strings.
+fn entity_tags(tags: &str) -> Vec<&str> {
+    let mut result = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in tags.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                result.push(tags[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(tags[start..].trim());
+    result.retain(|tag| !tag.is_empty());
+    result
+}
+
+/// HTTP dates have one second resolution.
+fn secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+    use std::time::Duration;
+
+    const ETAG: &str = "\"5f5e100.0-400\"";
+    const LAST_MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const EARLIER: &str = "Sun, 06 Nov 1994 08:49:36 GMT";
+
+    fn validators() -> Validators {
+        Validators {
+            etag: ETAG.to_string(),
+            last_modified: Some(UNIX_EPOCH + Duration::from_millis(784_111_777_500)),
+        }
+    }
+
+    fn headers(fields: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::new();
+        for (name, value) in fields {
+            headers.append(name, value);
+        }
+        headers
+    }
+
+    #[p_test(
+        (vec![], Precondition::Proceed),
+        (vec![("If-None-Match", ETAG)], Precondition::NotModified),
+        (vec![("If-None-Match", "W/\"5f5e100.0-400\"")], Precondition::NotModified),
+        (vec![("If-None-Match", "\"a\", \"5f5e100.0-400\"")], Precondition::NotModified),
+        (vec![("If-None-Match", "\"a\""), ("If-None-Match", ETAG)], Precondition::NotModified),
+        (vec![("If-None-Match", "*")], Precondition::NotModified),
+        (vec![("If-None-Match", "\"other\"")], Precondition::Proceed),
+        (vec![("If-Modified-Since", LAST_MODIFIED)], Precondition::NotModified),
+        (vec![("If-Modified-Since", EARLIER)], Precondition::Proceed),
+        (vec![("If-Modified-Since", "garbage")], Precondition::Proceed),
+        (vec![("If-None-Match", "\"other\""), ("If-Modified-Since", LAST_MODIFIED)], Precondition::Proceed),
+        (vec![("If-Match", ETAG)], Precondition::Proceed),
+        (vec![("If-Match", "*")], Precondition::Proceed),
+        (vec![("If-Match", "\"other\"")], Precondition::Failed),
+        (vec![("If-Match", "W/\"5f5e100.0-400\"")], Precondition::Failed),
+        (vec![("If-Unmodified-Since", LAST_MODIFIED)], Precondition::Proceed),
+        (vec![("If-Unmodified-Since", EARLIER)], Precondition::Failed),
+        (vec![("If-Match", ETAG), ("If-Unmodified-Since", EARLIER)], Precondition::Proceed),
+        (vec![("If-Match", "\"other\""), ("If-None-Match", ETAG)], Precondition::Failed),
+    )]
+    fn test_evaluate(fields: Vec<(&str, &str)>, expected: Precondition) {
+        assert_eq!(evaluate(&headers(&fields), &validators()), expected);
+    }
+
+    #[p_test(
+        (ETAG, true),
+        ("W/\"5f5e100.0-400\"", false),
+        ("\"other\"", false),
+        (LAST_MODIFIED, true),
+        (EARLIER, false),
+        ("garbage", false),
+    )]
+    fn test_if_range(value: &str, expected: bool) {
+        assert_eq!(if_range(value, &validators()), expected);
+    }
+
+    #[test]
+    fn test_weak_validator() {
+        let md = std::fs::metadata(std::env::temp_dir()).unwrap();
+        let validators = Validators::listing(&md);
+        assert!(validators.etag.starts_with("W/\""));
+        assert_eq!(validators.etag, Validators::listing(&md).etag);
+        let fields = [("If-None-Match", validators.etag.as_str())];
+        assert_eq!(
+            evaluate(&headers(&fields), &validators),
+            Precondition::NotModified
+        );
+        let fields = [("If-Match", validators.etag.as_str())];
+        assert_eq!(
+            evaluate(&headers(&fields), &validators),
+            Precondition::Failed
+        );
+    }
+
+    #[p_test(
+        (vec![], true, Precondition::Proceed),
+        (vec![], false, Precondition::Proceed),
+        (vec![("If-None-Match", "*")], true, Precondition::Failed),
+        (vec![("If-None-Match", "*")], false, Precondition::Proceed),
+        (vec![("If-None-Match", ETAG)], true, Precondition::Failed),
+        (vec![("If-None-Match", "\"other\"")], true, Precondition::Proceed),
+        (vec![("If-Match", "*")], true, Precondition::Proceed),
+        (vec![("If-Match", "*")], false, Precondition::Failed),
+        (vec![("If-Match", ETAG)], true, Precondition::Proceed),
+        (vec![("If-Match", "\"other\"")], true, Precondition::Failed),
+        (vec![("If-Unmodified-Since", EARLIER)], true, Precondition::Failed),
+        (vec![("If-Unmodified-Since", LAST_MODIFIED)], true, Precondition::Proceed),
+        (vec![("If-Modified-Since", LAST_MODIFIED)], true, Precondition::Proceed),
+    )]
+    fn test_evaluate_write(fields: Vec<(&str, &str)>, exists: bool, expected: Precondition) {
+        let validators = validators();
+        let validators = exists.then_some(&validators);
+        assert_eq!(evaluate_write(&headers(&fields), validators), expected);
+    }
+
+    #[test]
+    fn test_encoded() {
+        let validators = validators().encoded("gzip");
+        assert_eq!(validators.etag, "\"5f5e100.0-400-gzip\"");
+        let weak = Validators {
+            etag: "W/\"5f5e100.0\"".to_string(),
+            last_modified: None,
+        };
+        assert_eq!(weak.encoded("gzip").etag, "W/\"5f5e100.0-gzip\"");
+        let fields = [("If-None-Match", ETAG)];
+        assert_eq!(
+            evaluate(&headers(&fields), &validators),
+            Precondition::Proceed
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Converts days since 1970-01-01 into (year, month, day).
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts (year, month, day) into days since 1970-01-01.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Formats time as IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Formats time as ASN.1 GeneralizedTime, e.g. `19941106084937Z`, as used
+/// in certificates.
+pub(crate) fn generalized_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let rem = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parses HTTP-date in any of the three formats recipients must accept:
+/// IMF-fixdate, obsolete RFC 850 and asctime.
+pub(crate) fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let tokens: Vec<&str> = s.split_whitespace().collect();
+    let (day, month, year, time) = match tokens[..] {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => (day, month, year.parse().ok()?, time),
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut parts = date.split('-');
+            let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+            let year: i64 = year.parse().ok()?;
+            // two digit years: 70-99 are 19xx, 00-69 are 20xx
+            let year = if year < 70 { 2000 + year } else { 1900 + year };
+            (day, month, year, time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => (day, month, year.parse().ok()?, time),
+        _ => return None,
+    };
+
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let day: u32 = day.parse().ok()?;
+    let mut hms = time.split(':').map(|t| t.parse::<i64>().ok());
+    let (h, m, s) = (hms.next()??, hms.next()??, hms.next()??);
+    if hms.next().is_some() || !(1..=31).contains(&day) || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day) * 86400 + h * 3600 + m * 60 + s;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        (0, "Thu, 01 Jan 1970 00:00:00 GMT"),
+        (784111777, "Sun, 06 Nov 1994 08:49:37 GMT"),
+        (951782400, "Tue, 29 Feb 2000 00:00:00 GMT"),
+        (1700000000, "Tue, 14 Nov 2023 22:13:20 GMT"),
+    )]
+    fn test_http_date(secs: u64, expected: &str) {
+        let time = UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(http_date(time), expected);
+        assert_eq!(parse_http_date(expected), Some(time));
+    }
+
+    #[p_test(
+        (0, "19700101000000Z"),
+        (784111777, "19941106084937Z"),
+        (2524608000, "20500101000000Z"),
+    )]
+    fn test_generalized_time(secs: u64, expected: &str) {
+        let time = UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(generalized_time(time), expected);
+    }
+
+    #[p_test(
+        ("Sun, 06 Nov 1994 08:49:37 GMT"),
+        ("Sunday, 06-Nov-94 08:49:37 GMT"),
+        ("Sun Nov  6 08:49:37 1994"),
+    )]
+    fn test_parse_formats(input: &str) {
+        let expected = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(parse_http_date(input), Some(expected));
+    }
+
+    #[p_test(
+        (""),
+        ("yesterday"),
+        ("Sun, 06 Nov 1994 08:49:37 PST"),
+        ("Sun, 06 Foo 1994 08:49:37 GMT"),
+        ("Sun, 06 Nov 1994 25:49:37 GMT"),
+        ("Sun, 06 Nov 1994 08:49 GMT"),
+    )]
+    fn test_parse_invalid(input: &str) {
+        assert_eq!(parse_http_date(input), None);
+    }
+}
@@ -52,6 +52,22 @@ pub(crate) fn decode_percent(input: &str) -> Result<String, &'static str> {
     Ok(decoded)
 }
 
+/// Percent-encodes a decoded path so that it can be sent back in a header
+/// such as `Location`. Slashes and the characters allowed in path segments
+/// are kept as they are.
+pub(crate) fn encode_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => encoded.push(b as char),
+            b'-' | b'.' | b'_' | b'~' | b'/' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*'
+            | b'+' | b',' | b';' | b'=' | b':' | b'@' => encoded.push(b as char),
+            b => encoded.push_str(&format!("%{b:02X}")),
+        }
+    }
+    encoded
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -76,4 +92,15 @@ mod test {
     fn test_invalid(input: &str) {
         assert_eq!(decode_percent(input).unwrap_err(), MALFORMED_URI);
     }
+
+    #[p_test(
+        ("/a/b.txt", "/a/b.txt"),
+        ("/hello world/", "/hello%20world/"),
+        ("/아마존", "/%EC%95%84%EB%A7%88%EC%A1%B4"),
+        ("/a\r\nb?c#d%", "/a%0D%0Ab%3Fc%23d%25"),
+    )]
+    fn test_encode_path(path: &str, encoded: &str) {
+        assert_eq!(encode_path(path), encoded);
+        assert_eq!(decode_percent(encoded).unwrap(), path);
+    }
 }
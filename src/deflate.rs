@@ -0,0 +1,718 @@
+//! DEFLATE compressor (RFC 1951): LZ77 matching over a 32 KiB window,
+//! followed by whichever of a stored, fixed Huffman or dynamic Huffman block
+//! is the smallest.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, Write};
+
+/// Input collected before a block is compressed and written.
+const BLOCK_SIZE: usize = 64 * 1024;
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Candidates examined per position; longer chains compress slightly better
+/// but take more time.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+const NIL: usize = usize::MAX;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are written.
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const END_OF_BLOCK: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, dist: u16 },
+}
+
+impl Token {
+    /// Literal/length symbol and distance symbol of the token.
+    fn symbols(&self) -> (usize, Option<usize>) {
+        match *self {
+            Token::Literal(b) => (b as usize, None),
+            Token::Match { len, dist } => (257 + length_code(len), Some(dist_code(dist))),
+        }
+    }
+}
+
+fn length_code(len: u16) -> usize {
+    LENGTH_BASE.partition_point(|&base| base <= len) - 1
+}
+
+fn dist_code(dist: u16) -> usize {
+    DIST_BASE.partition_point(|&base| base <= dist) - 1
+}
+
+/// Packs bits least significant bit first, as DEFLATE requires.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, n: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+}
+
+/// Huffman code of an alphabet: code lengths and the codes, bit reversed so
+/// that they can be written least significant bit first.
+struct Code {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+impl Code {
+    fn from_lengths(lengths: Vec<u8>) -> Self {
+        let mut bl_count = [0u16; 16];
+        for &len in &lengths {
+            bl_count[len as usize] += 1;
+        }
+        bl_count[0] = 0;
+        let mut next_code = [0u16; 16];
+        let mut code = 0;
+        for bits in 1..16 {
+            code = (code + bl_count[bits - 1]) << 1;
+            next_code[bits] = code;
+        }
+        let codes = lengths
+            .iter()
+            .map(|&len| {
+                if len == 0 {
+                    return 0;
+                }
+                let code = next_code[len as usize];
+                next_code[len as usize] += 1;
+                code.reverse_bits() >> (16 - len)
+            })
+            .collect();
+        Code { lengths, codes }
+    }
+
+    fn fixed_literals() -> Self {
+        let mut lengths = vec![8; 144];
+        lengths.extend([9; 112]);
+        lengths.extend([7; 24]);
+        lengths.extend([8; 8]);
+        Code::from_lengths(lengths)
+    }
+
+    fn fixed_distances() -> Self {
+        Code::from_lengths(vec![5; 30])
+    }
+
+    fn write(&self, bits: &mut BitWriter, symbol: usize) {
+        bits.write(self.codes[symbol] as u32, self.lengths[symbol] as u32);
+    }
+
+    /// Bits needed for symbols with the given frequencies.
+    fn cost(&self, freqs: &[u32]) -> u64 {
+        freqs
+            .iter()
+            .zip(&self.lengths)
+            .map(|(&f, &len)| f as u64 * len as u64)
+            .sum()
+    }
+}
+
+/// Huffman code lengths for `freqs`, no longer than `max_bits`.
+/// Every alphabet gets at least two codes, so that the code is complete.
+fn code_lengths(freqs: &[u32], max_bits: u8) -> Vec<u8> {
+    let mut freqs = freqs.to_vec();
+    let used = freqs.iter().filter(|&&f| f > 0).count();
+    for f in freqs
+        .iter_mut()
+        .filter(|f| **f == 0)
+        .take(2usize.saturating_sub(used))
+    {
+        *f = 1;
+    }
+
+    loop {
+        let lengths = huffman_lengths(&freqs);
+        if lengths.iter().all(|&len| len <= max_bits) {
+            return lengths;
+        }
+        // flatten the distribution until the tree is shallow enough
+        for f in freqs.iter_mut().filter(|f| **f > 0) {
+            *f = (*f >> 1).max(1);
+        }
+    }
+}
+
+fn huffman_lengths(freqs: &[u32]) -> Vec<u8> {
+    // nodes are leaves (one per symbol) followed by internal nodes
+    let mut parent = vec![NIL; freqs.len()];
+    let mut heap = BinaryHeap::new();
+    for (symbol, &f) in freqs.iter().enumerate() {
+        if f > 0 {
+            heap.push(Reverse((f as u64, symbol)));
+        }
+    }
+    while heap.len() > 1 {
+        let Reverse((f1, n1)) = heap.pop().unwrap();
+        let Reverse((f2, n2)) = heap.pop().unwrap();
+        let node = parent.len();
+        parent.push(NIL);
+        parent[n1] = node;
+        parent[n2] = node;
+        heap.push(Reverse((f1 + f2, node)));
+    }
+
+    let mut depth = vec![0u8; parent.len()];
+    for node in (0..parent.len()).rev() {
+        if parent[node] != NIL {
+            depth[node] = depth[parent[node]] + 1;
+        }
+    }
+    (0..freqs.len())
+        .map(|symbol| if freqs[symbol] > 0 { depth[symbol] } else { 0 })
+        .collect()
+}
+
+/// Run-length encodes code lengths into code length symbols with their extra
+/// bits: 16 repeats the previous length, 17 and 18 repeat zero.
+fn encode_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut symbols = vec![];
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == len).count();
+        if len == 0 && run >= 11 {
+            let n = run.min(138);
+            symbols.push((18, (n - 11) as u8));
+            i += n;
+        } else if len == 0 && run >= 3 {
+            symbols.push((17, (run - 3) as u8));
+            i += run;
+        } else if len != 0 && run >= 4 {
+            symbols.push((len, 0));
+            let n = (run - 1).min(6);
+            symbols.push((16, (n - 3) as u8));
+            i += 1 + n;
+        } else {
+            symbols.push((len, 0));
+            i += 1;
+        }
+    }
+    symbols
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let v = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Earlier positions starting with the same three bytes, most recent first.
+struct HashChains {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl HashChains {
+    fn new(len: usize) -> Self {
+        HashChains {
+            head: vec![NIL; 1 << HASH_BITS],
+            prev: vec![NIL; len],
+        }
+    }
+
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(data, pos);
+            self.prev[pos] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+}
+
+/// Finds matches for `data[start..]`, which may refer back into
+/// `data[..start]`.
+fn tokenize(data: &[u8], start: usize) -> Vec<Token> {
+    let mut chains = HashChains::new(data.len());
+    for pos in start.saturating_sub(WINDOW_SIZE)..start {
+        chains.insert(data, pos);
+    }
+
+    let mut tokens = vec![];
+    let mut pos = start;
+    while pos < data.len() {
+        let max_len = (data.len() - pos).min(MAX_MATCH);
+        let (mut best_len, mut best_dist) = (0, 0);
+        if max_len >= MIN_MATCH {
+            let mut candidate = chains.head[hash(data, pos)];
+            let mut chain = 0;
+            while candidate != NIL && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_len, best_dist) = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                candidate = chains.prev[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            tokens.push(Token::Match {
+                len: best_len as u16,
+                dist: best_dist as u16,
+            });
+            for p in pos..pos + best_len {
+                chains.insert(data, p);
+            }
+            pos += best_len;
+        } else {
+            tokens.push(Token::Literal(data[pos]));
+            chains.insert(data, pos);
+            pos += 1;
+        }
+    }
+    tokens
+}
+
+/// Streaming DEFLATE encoder writing compressed data to `inner`.
+pub(crate) struct Deflater<W: Write> {
+    inner: W,
+    /// The last `WINDOW_SIZE` bytes already compressed, followed by input
+    /// not compressed yet.
+    data: Vec<u8>,
+    pending: usize,
+    bits: BitWriter,
+}
+
+impl<W: Write> Deflater<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Deflater {
+            inner,
+            data: vec![],
+            pending: 0,
+            bits: BitWriter::default(),
+        }
+    }
+
+    /// Compresses the remaining input as the final block and returns the
+    /// inner writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.write_block(true);
+        self.bits.align();
+        self.inner.write_all(&self.bits.out)?;
+        Ok(self.inner)
+    }
+
+    fn write_block(&mut self, last: bool) {
+        let tokens = tokenize(&self.data, self.pending);
+        let mut lit_freqs = vec![0u32; 286];
+        let mut dist_freqs = vec![0u32; 30];
+        for token in &tokens {
+            let (lit, dist) = token.symbols();
+            lit_freqs[lit] += 1;
+            if let Some(dist) = dist {
+                dist_freqs[dist] += 1;
+            }
+        }
+        lit_freqs[END_OF_BLOCK] = 1;
+        let extra_bits: u64 = tokens
+            .iter()
+            .map(|token| match *token {
+                Token::Literal(_) => 0,
+                Token::Match { len, dist } => {
+                    (LENGTH_EXTRA[length_code(len)] + DIST_EXTRA[dist_code(dist)]) as u64
+                }
+            })
+            .sum();
+
+        let raw = &self.data[self.pending..];
+        let stored_cost = if raw.len() <= u16::MAX as usize {
+            // header, alignment and the length fields
+            3 + 7 + 32 + raw.len() as u64 * 8
+        } else {
+            u64::MAX
+        };
+        let (fixed_lit, fixed_dist) = (Code::fixed_literals(), Code::fixed_distances());
+        let fixed_cost = 3 + fixed_lit.cost(&lit_freqs) + fixed_dist.cost(&dist_freqs) + extra_bits;
+        let dynamic = DynamicHeader::new(&lit_freqs, &dist_freqs);
+        let dynamic_cost = 3
+            + dynamic.cost()
+            + dynamic.lit.cost(&lit_freqs)
+            + dynamic.dist.cost(&dist_freqs)
+            + extra_bits;
+
+        self.bits.write(last as u32, 1);
+        if stored_cost <= fixed_cost.min(dynamic_cost) {
+            self.bits.write(0b00, 2);
+            self.bits.align();
+            let len = raw.len() as u16;
+            self.bits.write(len as u32, 16);
+            self.bits.write(!len as u32, 16);
+            self.bits.out.extend_from_slice(raw);
+        } else if fixed_cost <= dynamic_cost {
+            self.bits.write(0b01, 2);
+            write_tokens(&mut self.bits, &tokens, &fixed_lit, &fixed_dist);
+        } else {
+            self.bits.write(0b10, 2);
+            dynamic.write(&mut self.bits);
+            write_tokens(&mut self.bits, &tokens, &dynamic.lit, &dynamic.dist);
+        }
+
+        // keep the window for matches in the next block
+        let keep_from = self.data.len().saturating_sub(WINDOW_SIZE);
+        self.data.drain(..keep_from);
+        self.pending = self.data.len();
+    }
+
+    fn write_out(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.bits.out)?;
+        self.bits.out.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Deflater<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - (self.data.len() - self.pending));
+        self.data.extend_from_slice(&buf[..len]);
+        if self.data.len() - self.pending == BLOCK_SIZE {
+            self.write_block(false);
+            self.write_out()?;
+        }
+        Ok(len)
+    }
+
+    /// Compresses the input so far and appends an empty stored block, so that
+    /// everything written so far can be decompressed by the receiver.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.data.len() > self.pending {
+            self.write_block(false);
+        }
+        self.bits.write(0, 3);
+        self.bits.align();
+        self.bits.write(0x0000, 16);
+        self.bits.write(0xffff, 16);
+        self.write_out()?;
+        self.inner.flush()
+    }
+}
+
+fn write_tokens(bits: &mut BitWriter, tokens: &[Token], lit: &Code, dist: &Code) {
+    for token in tokens {
+        match *token {
+            Token::Literal(b) => lit.write(bits, b as usize),
+            Token::Match { len, dist: d } => {
+                let lc = length_code(len);
+                lit.write(bits, 257 + lc);
+                bits.write((len - LENGTH_BASE[lc]) as u32, LENGTH_EXTRA[lc] as u32);
+                let dc = dist_code(d);
+                dist.write(bits, dc);
+                bits.write((d - DIST_BASE[dc]) as u32, DIST_EXTRA[dc] as u32);
+            }
+        }
+    }
+    lit.write(bits, END_OF_BLOCK);
+}
+
+/// Codes of a dynamic Huffman block and how they are described in its
+/// header.
+struct DynamicHeader {
+    lit: Code,
+    dist: Code,
+    hlit: usize,
+    hdist: usize,
+    clen: Code,
+    hclen: usize,
+    lengths: Vec<(u8, u8)>,
+}
+
+impl DynamicHeader {
+    fn new(lit_freqs: &[u32], dist_freqs: &[u32]) -> Self {
+        let lit = Code::from_lengths(code_lengths(lit_freqs, 15));
+        let dist = Code::from_lengths(code_lengths(dist_freqs, 15));
+        let hlit = 257.max(lit.lengths.iter().rposition(|&l| l > 0).unwrap_or(0) + 1);
+        let hdist = 1.max(dist.lengths.iter().rposition(|&l| l > 0).unwrap_or(0) + 1);
+
+        let mut all = lit.lengths[..hlit].to_vec();
+        all.extend_from_slice(&dist.lengths[..hdist]);
+        let lengths = encode_lengths(&all);
+        let mut clen_freqs = vec![0u32; 19];
+        for &(symbol, _) in &lengths {
+            clen_freqs[symbol as usize] += 1;
+        }
+        let clen = Code::from_lengths(code_lengths(&clen_freqs, 7));
+        let hclen = 4.max(
+            CLEN_ORDER
+                .iter()
+                .rposition(|&s| clen.lengths[s] > 0)
+                .unwrap_or(0)
+                + 1,
+        );
+
+        DynamicHeader {
+            lit,
+            dist,
+            hlit,
+            hdist,
+            clen,
+            hclen,
+            lengths,
+        }
+    }
+
+    fn cost(&self) -> u64 {
+        let lengths: u64 = self
+            .lengths
+            .iter()
+            .map(|&(symbol, _)| self.clen.lengths[symbol as usize] as u64 + extra_bits(symbol))
+            .sum();
+        14 + 3 * self.hclen as u64 + lengths
+    }
+
+    fn write(&self, bits: &mut BitWriter) {
+        bits.write((self.hlit - 257) as u32, 5);
+        bits.write((self.hdist - 1) as u32, 5);
+        bits.write((self.hclen - 4) as u32, 4);
+        for &symbol in &CLEN_ORDER[..self.hclen] {
+            bits.write(self.clen.lengths[symbol] as u32, 3);
+        }
+        for &(symbol, extra) in &self.lengths {
+            self.clen.write(bits, symbol as usize);
+            bits.write(extra as u32, extra_bits(symbol) as u32);
+        }
+    }
+}
+
+/// Extra bits following a code length symbol.
+fn extra_bits(symbol: u8) -> u64 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use p_test::p_test;
+    use std::collections::HashMap;
+
+    /// Symbols by code length and code.
+    fn table(lengths: &[u8]) -> HashMap<(u8, u16), usize> {
+        let code = Code::from_lengths(lengths.to_vec());
+        (0..lengths.len())
+            .filter(|&s| code.lengths[s] > 0)
+            .map(|s| ((code.lengths[s], code.codes[s]), s))
+            .collect()
+    }
+
+    /// Minimal DEFLATE decoder to check the encoder against.
+    pub(crate) fn inflate(data: &[u8]) -> Vec<u8> {
+        struct Bits<'a> {
+            data: &'a [u8],
+            pos: usize,
+        }
+        impl Bits<'_> {
+            fn bit(&mut self) -> u32 {
+                let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+                self.pos += 1;
+                bit as u32
+            }
+            fn bits(&mut self, n: u32) -> u32 {
+                (0..n).fold(0, |v, i| v | self.bit() << i)
+            }
+            fn symbol(&mut self, table: &HashMap<(u8, u16), usize>) -> usize {
+                let (mut value, mut len) = (0u16, 0u8);
+                loop {
+                    value |= (self.bit() as u16) << len;
+                    len += 1;
+                    if let Some(&symbol) = table.get(&(len, value)) {
+                        return symbol;
+                    }
+                }
+            }
+        }
+
+        let mut bits = Bits { data, pos: 0 };
+        let mut out: Vec<u8> = vec![];
+        loop {
+            let last = bits.bit();
+            let (lit, dist) = match bits.bits(2) {
+                0 => {
+                    bits.pos = bits.pos.div_ceil(8) * 8;
+                    let len = bits.bits(16);
+                    assert_eq!(bits.bits(16), !len & 0xffff);
+                    for _ in 0..len {
+                        out.push(bits.bits(8) as u8);
+                    }
+                    if last == 1 {
+                        return out;
+                    }
+                    continue;
+                }
+                1 => (Code::fixed_literals().lengths, vec![5; 30]),
+                2 => {
+                    let hlit = bits.bits(5) as usize + 257;
+                    let hdist = bits.bits(5) as usize + 1;
+                    let hclen = bits.bits(4) as usize + 4;
+                    let mut clen = vec![0u8; 19];
+                    for &s in &CLEN_ORDER[..hclen] {
+                        clen[s] = bits.bits(3) as u8;
+                    }
+                    let clen = table(&clen);
+                    let mut lengths = vec![];
+                    while lengths.len() < hlit + hdist {
+                        match bits.symbol(&clen) {
+                            16 => {
+                                let prev = *lengths.last().unwrap();
+                                let n = 3 + bits.bits(2);
+                                lengths.extend((0..n).map(|_| prev));
+                            }
+                            17 => lengths.extend((0..3 + bits.bits(3)).map(|_| 0)),
+                            18 => lengths.extend((0..11 + bits.bits(7)).map(|_| 0)),
+                            len => lengths.push(len as u8),
+                        }
+                    }
+                    (lengths[..hlit].to_vec(), lengths[hlit..].to_vec())
+                }
+                _ => panic!("invalid block type"),
+            };
+            let (lit, dist) = (table(&lit), table(&dist));
+            loop {
+                match bits.symbol(&lit) {
+                    END_OF_BLOCK => break,
+                    b @ 0..=255 => out.push(b as u8),
+                    s => {
+                        let lc = s - 257;
+                        let len =
+                            LENGTH_BASE[lc] as usize + bits.bits(LENGTH_EXTRA[lc] as u32) as usize;
+                        let dc = bits.symbol(&dist);
+                        let d = DIST_BASE[dc] as usize + bits.bits(DIST_EXTRA[dc] as u32) as usize;
+                        for _ in 0..len {
+                            out.push(out[out.len() - d]);
+                        }
+                    }
+                }
+            }
+            if last == 1 {
+                return out;
+            }
+        }
+    }
+
+    pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut deflater = Deflater::new(vec![]);
+        deflater.write_all(data).unwrap();
+        deflater.finish().unwrap()
+    }
+
+    fn sample_text(len: usize) -> Vec<u8> {
+        let words = [
+            "lorem",
+            "ipsum",
+            "dolor",
+            "sit",
+            "amet",
+            "{\"key\": ",
+            "[1, 2, 3]",
+            "\n",
+        ];
+        let mut seed = 1u32;
+        let mut text = vec![];
+        while text.len() < len {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            text.extend_from_slice(words[(seed >> 16) as usize % words.len()].as_bytes());
+            text.push(b' ');
+        }
+        text.truncate(len);
+        text
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut seed = 7u64;
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect()
+    }
+
+    #[p_test(
+        (vec![]),
+        (b"a".to_vec()),
+        (b"hello hello hello hello".to_vec()),
+        (vec![0; 100_000]),
+        (sample_text(1000)),
+        (sample_text(200_000)),
+        (noise(70_000)),
+    )]
+    fn test_roundtrip(data: Vec<u8>) {
+        assert_eq!(inflate(&deflate(&data)), data);
+    }
+
+    #[test]
+    fn test_compresses_text() {
+        let text = sample_text(100_000);
+        assert!(deflate(&text).len() < text.len() / 4);
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut deflater = Deflater::new(vec![]);
+        deflater.write_all(b"first part, ").unwrap();
+        deflater.flush().unwrap();
+        deflater.write_all(b"second part").unwrap();
+        let compressed = deflater.finish().unwrap();
+        assert_eq!(inflate(&compressed), b"first part, second part");
+    }
+
+    #[p_test(
+        (vec![1, 1, 1, 1, 1, 0, 0, 0], vec![(1, 0), (16, 1), (17, 0)]),
+        (vec![0; 140], vec![(18, 127), (0, 0), (0, 0)]),
+        (vec![2, 2, 3], vec![(2, 0), (2, 0), (3, 0)]),
+    )]
+    fn test_encode_lengths(lengths: Vec<u8>, expected: Vec<(u8, u8)>) {
+        assert_eq!(encode_lengths(&lengths), expected);
+    }
+}
@@ -0,0 +1,106 @@
+/// Matches `text` against a shell-style glob `pattern`.
+/// `*` matches any run of characters within a path segment, `**` also
+/// crosses `/`, `?` matches one character other than `/`, and `[...]`
+/// matches one character of a set such as `[abc]`, `[a-z]` or `[!.]`.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches(&pattern, &text)
+}
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        ['*', rest @ ..] => {
+            let segment = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=segment).any(|i| matches(rest, &text[i..]))
+        }
+        ['?', rest @ ..] => match text {
+            [c, text @ ..] if *c != '/' => matches(rest, text),
+            _ => false,
+        },
+        ['[', rest @ ..] => match (class(rest), text) {
+            (Some((set, rest)), [c, text @ ..]) => {
+                *c != '/' && in_class(set, *c) && matches(rest, text)
+            }
+            // an unclosed `[` is taken literally
+            (None, ['[', text @ ..]) => matches(rest, text),
+            _ => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text @ ..] if c == p => matches(rest, text),
+            _ => false,
+        },
+    }
+}
+
+/// Splits off the body of a character class, without the closing `]`.
+/// A `]` right after the opening (or after `!`) belongs to the set.
+fn class(pattern: &[char]) -> Option<(&[char], &[char])> {
+    let skip = match pattern {
+        ['!', ']', ..] => 2,
+        ['!', ..] | [']', ..] => 1,
+        _ => 0,
+    };
+    let end = skip + pattern[skip..].iter().position(|&c| c == ']')?;
+    Some((&pattern[..end], &pattern[end + 1..]))
+}
+
+fn in_class(set: &[char], c: char) -> bool {
+    let (negated, mut set) = match set {
+        ['!', rest @ ..] => (true, rest),
+        _ => (false, set),
+    };
+    let mut found = false;
+    while !set.is_empty() {
+        match set {
+            [from, '-', to, rest @ ..] => {
+                found |= (*from..=*to).contains(&c);
+                set = rest;
+            }
+            [x, rest @ ..] => {
+                found |= *x == c;
+                set = rest;
+            }
+            [] => unreachable!(),
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        ("*.key", "server.key", true),
+        ("*.key", "server.key.pub", false),
+        ("*.key", "certs/server.key", false),
+        ("**/*.key", "certs/server.key", true),
+        ("**.key", "certs/server.key", true),
+        ("docs/*", "docs/a.md", true),
+        ("docs/*", "docs/a/b.md", false),
+        ("docs/**", "docs/a/b.md", true),
+        (".git", ".git", true),
+        (".git", ".github", false),
+        (".*", ".env", true),
+        (".*", "env", false),
+        ("?.txt", "a.txt", true),
+        ("?.txt", "ab.txt", false),
+        ("a?b", "a/b", false),
+        ("[abc].txt", "b.txt", true),
+        ("[abc].txt", "d.txt", false),
+        ("[a-c].txt", "c.txt", true),
+        ("[!.]*", ".env", false),
+        ("[!.]*", "env", true),
+        ("[]]", "]", true),
+        ("[", "[", true),
+        ("", "", true),
+        ("*", "", true),
+    )]
+    fn test_glob_match(pattern: &str, text: &str, expected: bool) {
+        assert_eq!(glob_match(pattern, text), expected);
+    }
+}
@@ -0,0 +1,149 @@
+/// HTTP header fields as a case-insensitive multimap.
+/// Fields keep the order in which they were received, and the same name may
+/// appear more than once (e.g. several `Accept-Encoding` lines).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a field, keeping any existing fields with the same name.
+    pub(crate) fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    /// Returns the value of the first field with the given name.
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the values of all fields with the given name combined into one
+    /// comma-separated list, the way list-based fields may be split over lines.
+    pub(crate) fn get_joined(&self, name: &str) -> Option<String> {
+        let values: Vec<&str> = self
+            .fields
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect();
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Checks whether any field with the given name lists `token` among its
+    /// comma-separated values, ignoring case.
+    pub(crate) fn contains_token(&self, name: &str, token: &str) -> bool {
+        self.fields
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .flat_map(|(_, v)| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Adds a field from a header line without the line terminator.
+    /// Obsolete line folding is unfolded into a single space.
+    pub(crate) fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        if line.starts_with([' ', '\t']) {
+            return match self.fields.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                    Ok(())
+                }
+                None => Err(format!("Invalid header line: {line}")),
+            };
+        }
+        match line.split_once(':') {
+            Some((name, value)) if is_token(name) => {
+                self.append(name, value.trim());
+                Ok(())
+            }
+            _ => Err(format!("Invalid header line: {line}")),
+        }
+    }
+}
+
+/// Checks whether `s` is a valid header field name (RFC 9110 `token`).
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    fn parse(input: &str) -> Result<Headers, String> {
+        let mut headers = Headers::new();
+        for line in input.lines() {
+            headers.parse_line(line)?;
+        }
+        Ok(headers)
+    }
+
+    #[test]
+    fn test_read_headers() {
+        let headers = parse("Host: localhost:3000\r\nAccept: */*").unwrap();
+        assert_eq!(headers.fields.len(), 2);
+        assert_eq!(headers.get("host"), Some("localhost:3000"));
+        assert_eq!(headers.get("ACCEPT"), Some("*/*"));
+        assert_eq!(headers.get("Connection"), None);
+    }
+
+    #[test]
+    fn test_multiple_values() {
+        let headers = parse("Accept: text/html\nX-Test: a\naccept: text/plain").unwrap();
+        assert_eq!(headers.fields.len(), 3);
+        assert_eq!(
+            headers.get_joined("accept").as_deref(),
+            Some("text/html, text/plain")
+        );
+        assert_eq!(headers.get_joined("Host"), None);
+        assert_eq!(headers.get("accept"), Some("text/html"));
+    }
+
+    #[p_test(
+        ("Connection: close", "close", true),
+        ("Connection: Keep-Alive", "keep-alive", true),
+        ("Connection: upgrade, CLOSE", "close", true),
+        ("Connection: upgrade\r\nconnection: close", "close", true),
+        ("Connection: closed", "close", false),
+        ("X-Connection: close", "close", false),
+    )]
+    fn test_contains_token(input: &str, token: &str, expected: bool) {
+        let headers = parse(input).unwrap();
+        assert_eq!(headers.contains_token("Connection", token), expected);
+    }
+
+    #[test]
+    fn test_folded_value() {
+        let headers = parse("X-Long: first\r\n  second").unwrap();
+        assert_eq!(headers.get("x-long"), Some("first second"));
+    }
+
+    #[p_test(
+        ("NoColon"),
+        ("Bad Name: value"),
+        (": empty name"),
+        (" folded first"),
+    )]
+    fn test_invalid(input: &str) {
+        assert!(parse(input).is_err());
+    }
+}
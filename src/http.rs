@@ -1,68 +1,235 @@
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
-use std::net::TcpStream;
+use std::io::{self, BufRead, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use colorust::Color;
 
-use crate::decode::decode_percent;
-use crate::mime::mime;
+use crate::chunked::ChunkedWriter;
+use crate::compress::{Coding, Encoder, negotiate, preferred};
+use crate::conditional::{Precondition, Validators, evaluate, if_range};
+use crate::decode::{decode_percent, encode_path};
+use crate::headers::Headers;
+use crate::mime::{compressible, mime};
+use crate::range::{Ranges, parse_range};
+use crate::resolve::is_symlink;
+use crate::timeout::is_timeout;
+use crate::visibility::Visibility;
+use crate::xml::escape;
 
-/// Represents HTTP Request. Currently, only interested in `method` and `path`.
-/// Headers in requests are out of interest, hence will be ignored.
-/// Though it has `method` field, only supported HTTP method will be GET, and
-/// other methods in requests will cause error HTTP-405.
+/// Represents HTTP Request: the request line and the header fields.
+/// `path` is percent-decoded, while `query` is kept as it was received
+/// since its encoding depends on the consumer.
+/// GET, HEAD and OPTIONS are always supported. POST, PUT, DELETE, MKCOL and
+/// MOVE need writing to be enabled, PROPFIND needs WebDAV, and COPY and
+/// PROPPATCH need both; other methods in requests will cause error HTTP-405.
+#[derive(Debug)]
 pub(crate) struct Request {
     pub method: String,
     pub path: String,
+    pub query: Option<String>,
+    pub version: String,
+    pub headers: Headers,
 }
 
 impl TryFrom<String> for Request {
     type Error = String;
     fn try_from(s: String) -> Result<Self, Self::Error> {
-        let v = s.split_whitespace().take(2).collect::<Vec<&str>>();
-        if let [method, path] = &v[..] {
-            let decoded = decode_percent(trim_path(path))?;
-            Ok(Request {
-                method: method.to_string(),
-                path: decoded,
-            })
-        } else if s.is_empty() {
-            Ok(Request {
-                method: "".to_string(),
-                path: "".to_string(),
-            })
-        } else {
-            Err(format!("Fail to get request method/path\n{s}"))
+        let v = s.split_whitespace().collect::<Vec<&str>>();
+        let (method, target, version) = match &v[..] {
+            [method, target] => (method, target, "HTTP/1.0"),
+            [method, target, version] if version.starts_with("HTTP/") => (method, target, *version),
+            [] => {
+                return Ok(Request {
+                    method: "".to_string(),
+                    path: "".to_string(),
+                    query: None,
+                    version: "".to_string(),
+                    headers: Headers::new(),
+                });
+            }
+            _ => return Err(format!("Fail to get request method/path\n{s}")),
+        };
+        let (path, query) = split_target(target);
+        Ok(Request {
+            method: method.to_string(),
+            path: decode_percent(path)?,
+            query: query.map(str::to_string),
+            version: version.to_string(),
+            headers: Headers::new(),
+        })
+    }
+}
+
+/// Longest request line accepted, answered with 414 otherwise.
+const MAX_REQUEST_LINE: usize = 8 * 1024;
+/// Largest header section accepted, answered with 431 otherwise.
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// Reasons a request can't be read, each answered with its own status code.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RequestError {
+    Malformed(String),
+    Timeout,
+    UriTooLong,
+    HeadersTooLarge,
+    PayloadTooLarge,
+    ExpectationFailed,
+    UnsupportedTransferCoding,
+}
+
+impl RequestError {
+    pub(crate) fn code(&self) -> u16 {
+        match self {
+            RequestError::Malformed(_) => 400,
+            RequestError::Timeout => 408,
+            RequestError::UriTooLong => 414,
+            RequestError::HeadersTooLarge => 431,
+            RequestError::PayloadTooLarge => 413,
+            RequestError::ExpectationFailed => 417,
+            RequestError::UnsupportedTransferCoding => 501,
         }
     }
+
+    pub(crate) fn reason(&self) -> &str {
+        match self {
+            RequestError::Malformed(reason) => reason,
+            RequestError::Timeout => "Request was not received in time",
+            RequestError::UriTooLong => "Request line is too long",
+            RequestError::HeadersTooLarge => "Request header section is too large",
+            RequestError::PayloadTooLarge => "Request body is too large",
+            RequestError::ExpectationFailed => "Only 100-continue can be expected",
+            RequestError::UnsupportedTransferCoding => "Only chunked transfer coding is supported",
+        }
+    }
+}
+
+impl From<String> for RequestError {
+    fn from(reason: String) -> Self {
+        RequestError::Malformed(reason)
+    }
+}
+
+/// Reads a line of at most `limit` bytes including the line terminator.
+/// `Ok(None)` is returned if the line is longer. At the end of the stream,
+/// the bytes read so far are returned, which may be none.
+pub(crate) fn read_line<R: BufRead>(reader: &mut R, limit: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut line = vec![];
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(Some(line));
+        }
+        let (len, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), false),
+        };
+        if line.len() + len > limit {
+            return Ok(None);
+        }
+        line.extend_from_slice(&available[..len]);
+        reader.consume(len);
+        if done {
+            return Ok(Some(line));
+        }
+    }
+}
+
+fn read_error(e: io::Error) -> RequestError {
+    if is_timeout(&e) {
+        RequestError::Timeout
+    } else {
+        RequestError::Malformed(format!("Fail to read request: {e}"))
+    }
 }
 
 impl Request {
-    pub fn get(stream: &mut TcpStream) -> Result<Self, String> {
-        let mut line = String::new();
-        if BufReader::new(stream).read_line(&mut line).is_ok() {
-            Request::try_from(line)
+    /// Reads the next request from a connection. Empty lines in front of the
+    /// request line are skipped. When the peer closes the connection before a
+    /// request line arrives, an empty request is returned.
+    pub fn get<R: BufRead>(reader: &mut R) -> Result<Self, RequestError> {
+        let mut line = vec![];
+        while line.trim_ascii().is_empty() {
+            line = match read_line(reader, MAX_REQUEST_LINE) {
+                Ok(Some(line)) if line.is_empty() => return Ok(Request::try_from(String::new())?),
+                Ok(Some(line)) => line,
+                Ok(None) => return Err(RequestError::UriTooLong),
+                Err(e) => return Err(read_error(e)),
+            };
+        }
+        let line = String::from_utf8(line).map_err(|_| "Request line is not UTF-8".to_string())?;
+        let mut request = Request::try_from(line)?;
+
+        let mut remaining = MAX_HEADER_SIZE;
+        loop {
+            let line = match read_line(reader, remaining) {
+                Ok(Some(line)) if !line.ends_with(b"\n") => {
+                    return Err("Unexpected end of header section".to_string().into());
+                }
+                Ok(Some(line)) => line,
+                Ok(None) => return Err(RequestError::HeadersTooLarge),
+                Err(e) => return Err(read_error(e)),
+            };
+            remaining -= line.len();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                break;
+            }
+            request.headers.parse_line(line)?;
+        }
+
+        if request.version == "HTTP/1.1" && !request.headers.contains("Host") {
+            return Err("Missing Host header".to_string().into());
+        }
+        Ok(request)
+    }
+
+    /// Whether the client asked to keep the connection open after this
+    /// request. HTTP/1.1 connections are persistent unless `Connection: close`
+    /// is sent, HTTP/1.0 connections only with `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        if self.headers.contains_token("Connection", "close") {
+            false
+        } else if self.version == "HTTP/1.0" {
+            self.headers.contains_token("Connection", "keep-alive")
         } else {
-            Err("Fail to get request line".into())
+            true
         }
     }
+
+    /// Whether the client waits for `100 Continue` before sending the body.
+    /// HTTP/1.0 clients don't know interim responses.
+    pub fn expects_continue(&self) -> bool {
+        self.version != "HTTP/1.0"
+            && self
+                .headers
+                .get("Expect")
+                .is_some_and(|expect| expect.trim().eq_ignore_ascii_case("100-continue"))
+    }
 }
 
-fn mime_type(path: &Path) -> &'static str {
+pub(crate) fn mime_type(path: &Path) -> &'static str {
     mime(path.extension().and_then(|s| s.to_str()).unwrap_or(""))
 }
 
-fn trim_path(input: &str) -> &str {
-    input.split(&['#', '?']).next().unwrap()
+/// Splits the request target into the path and the optional query string,
+/// dropping the fragment if any.
+fn split_target(input: &str) -> (&str, Option<&str>) {
+    let input = input.split('#').next().unwrap();
+    match input.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (input, None),
+    }
 }
 
 trait WriteFile {
-    fn write_file(&mut self, file: fs::File) -> io::Result<usize>;
+    fn write_file<R: Read>(&mut self, file: R) -> io::Result<usize>;
 }
 
-impl WriteFile for TcpStream {
-    fn write_file(&mut self, mut file: File) -> io::Result<usize> {
+impl<W: Write + ?Sized> WriteFile for W {
+    fn write_file<R: Read>(&mut self, mut file: R) -> io::Result<usize> {
         const BUF_SIZE: usize = 8 * 1024;
         let mut buf = [0; BUF_SIZE];
         let mut written = 0;
@@ -79,59 +246,532 @@ impl WriteFile for TcpStream {
     }
 }
 
+/// Keep-alive parameters announced to the client in `Keep-Alive` header.
+pub(crate) struct KeepAlive {
+    pub timeout: u64,
+    pub max: usize,
+}
+
+/// Per-response details decided by the connection handler rather than by the
+/// response itself.
+pub(crate) struct Exchange<'a> {
+    /// Header fields of the request being answered.
+    pub headers: &'a Headers,
+    /// `None` if the connection will be closed after the response.
+    pub keep_alive: Option<KeepAlive>,
+    /// Responding to a HEAD request: the head is written as for GET, but
+    /// the body is left out.
+    pub head: bool,
+    /// Responses may be compressed if the client accepts it.
+    pub compress: bool,
+    /// The client understands the chunked transfer coding, i.e. it sent an
+    /// HTTP/1.1 request.
+    pub chunked: bool,
+    /// Seconds for which browsers should only use HTTPS with the server, if
+    /// they are to be told with `Strict-Transport-Security`. Only ever sent
+    /// over TLS.
+    pub hsts: Option<u64>,
+}
+
+fn reason_phrase(code: u16) -> &'static str {
+    match code {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        301 => "Moved Permanently",
+        303 => "See Other",
+        304 => "Not Modified",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        412 => "Precondition Failed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// Writes the status line and the header fields, followed by the connection
+/// management fields and the empty line ending the head.
+fn write_head<W: Write>(
+    stream: &mut W,
+    code: u16,
+    headers: &Headers,
+    exchange: &Exchange,
+) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {code} {}\r\n", reason_phrase(code))?;
+    for (name, value) in headers.iter() {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    match &exchange.keep_alive {
+        Some(KeepAlive { timeout, max }) => {
+            stream.write_all(b"Connection: keep-alive\r\n")?;
+            write!(stream, "Keep-Alive: timeout={timeout}, max={max}\r\n")?;
+        }
+        None => stream.write_all(b"Connection: close\r\n")?,
+    }
+    if let Some(max_age) = exchange.hsts {
+        write!(stream, "Strict-Transport-Security: max-age={max_age}\r\n")?;
+    }
+    stream.write_all(b"\r\n")
+}
+
 pub(crate) enum Response<'a> {
-    File(&'a Path),
-    Directory(&'a str, &'a Path),
-    Error { code: u16, body: &'a str },
+    /// A file under the root, served precompressed if a visible variant
+    /// exists.
+    File(&'a Path, &'a Path, &'a Visibility),
+    /// Listing of a directory, with an upload form if uploads are enabled.
+    Directory(&'a Path, &'a Path, &'a Visibility, bool),
+    /// A file has been written, and was `created` rather than replaced.
+    Stored {
+        path: &'a Path,
+        created: bool,
+    },
+    /// A file or directory has been deleted.
+    Deleted,
+    /// Methods served, and whether WebDAV is.
+    Options {
+        allow: &'a str,
+        dav: bool,
+    },
+    /// A WebDAV `multistatus` document.
+    MultiStatus(&'a str),
+    /// A WebDAV `error` document, naming the precondition that failed.
+    DavError {
+        code: u16,
+        body: &'a str,
+    },
+    MethodNotAllowed {
+        allow: &'a str,
+    },
+    /// The client is over its limits, and may retry after `retry_after`
+    /// seconds.
+    TooManyRequests {
+        retry_after: u64,
+    },
+    /// Credentials are required, for the protection space `realm`.
+    Unauthorized {
+        realm: &'a str,
+    },
+    Redirect {
+        code: u16,
+        location: &'a str,
+    },
+    Error {
+        code: u16,
+        body: &'a str,
+    },
 }
 
 impl<'a> Response<'a> {
-    pub(crate) fn file(path: &'a Path) -> Self {
-        Response::File(path)
+    pub(crate) fn file(base: &'a Path, path: &'a Path, visibility: &'a Visibility) -> Self {
+        Response::File(base, path, visibility)
     }
 
-    pub(crate) fn directory(base: &'a str, path: &'a Path) -> Self {
-        Response::Directory(base, path)
+    pub(crate) fn directory(
+        base: &'a Path,
+        path: &'a Path,
+        visibility: &'a Visibility,
+        upload: bool,
+    ) -> Self {
+        Response::Directory(base, path, visibility, upload)
+    }
+
+    pub(crate) fn stored(path: &'a Path, created: bool) -> Self {
+        Response::Stored { path, created }
+    }
+
+    pub(crate) fn deleted() -> Self {
+        Response::Deleted
+    }
+
+    pub(crate) fn options(allow: &'a str, dav: bool) -> Self {
+        Response::Options { allow, dav }
+    }
+
+    pub(crate) fn multi_status(body: &'a str) -> Self {
+        Response::MultiStatus(body)
+    }
+
+    pub(crate) fn dav_error(code: u16, body: &'a str) -> Self {
+        Response::DavError { code, body }
+    }
+
+    pub(crate) fn method_not_allowed(allow: &'a str) -> Self {
+        Response::MethodNotAllowed { allow }
+    }
+
+    pub(crate) fn too_many_requests(retry_after: u64) -> Self {
+        Response::TooManyRequests { retry_after }
+    }
+
+    pub(crate) fn unauthorized(realm: &'a str) -> Self {
+        Response::Unauthorized { realm }
+    }
+
+    pub(crate) fn redirect(code: u16, location: &'a str) -> Self {
+        Response::Redirect { code, location }
     }
 
     pub(crate) fn error(code: u16, body: &'a str) -> Self {
         Response::Error { code, body }
     }
 
-    pub(crate) fn send_to(&self, stream: &mut TcpStream) -> io::Result<usize> {
+    pub(crate) fn send_to<W: Write>(
+        &self,
+        stream: &mut W,
+        exchange: &Exchange,
+    ) -> io::Result<usize> {
         match *self {
-            Response::File(path) => send_file(stream, path),
-            Response::Directory(base, path) => show_dir(stream, base, path),
+            Response::File(base, path, visibility) => {
+                send_file(stream, exchange, path, |sidecar| {
+                    visibility.allows(base, sidecar)
+                })
+            }
+            Response::Directory(base, path, visibility, upload) => {
+                show_dir(stream, exchange, base, path, visibility, upload)
+            }
+            Response::Stored { path, created } => send_stored(stream, exchange, path, created),
+            Response::Deleted => {
+                write_head(stream, 204, &Headers::new(), exchange)?;
+                Ok(0)
+            }
+            Response::Options { allow, dav } => send_options(stream, exchange, allow, dav),
+            Response::MultiStatus(body) => send_xml(stream, exchange, 207, body),
+            Response::DavError { code, body } => send_xml(stream, exchange, code, body),
+            Response::MethodNotAllowed { allow } => http_405(stream, exchange, allow),
+            Response::TooManyRequests { retry_after } => http_429(stream, exchange, retry_after),
+            Response::Unauthorized { realm } => http_401(stream, exchange, realm),
+            Response::Redirect { code, location } => {
+                send_redirect(stream, exchange, code, location)
+            }
             Response::Error { code, body } => match code {
-                400 => http_400(stream, body),
-                404 => http_404(stream, body),
-                405 => http_405(stream),
+                400 => http_400(stream, exchange, body),
+                403 => http_403(stream, exchange, body),
+                404 => http_404(stream, exchange, body),
+                408 | 409 | 412 | 413 | 414 | 415 | 417 | 431 | 500 | 501 => {
+                    http_error(stream, exchange, code, body)
+                }
+                503 => http_503(stream, exchange, body),
                 _ => Err(io::Error::other(body)),
             },
         }
     }
 }
 
-pub(crate) fn send_file(stream: &mut TcpStream, path: &Path) -> io::Result<usize> {
-    let f = File::open(path)?;
-    let md = f.metadata()?;
-    let mime_type = mime_type(path);
-
-    stream.write_all(b"HTTP/1.1 200 OK\n")?;
-    stream.write_all(b"Cache-Control: max-age=3600\n")?;
+pub(crate) fn content_type(mime_type: &str) -> String {
     if mime_type.contains("text") {
-        stream.write_all(format!("Content-Type: {mime_type}; charset=utf-8\n").as_bytes())?;
+        format!("{mime_type}; charset=utf-8")
     } else {
-        stream.write_all(format!("Content-Type: {mime_type}\n").as_bytes())?;
+        mime_type.to_string()
+    }
+}
+
+pub(crate) fn send_file<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    path: &Path,
+    visible: impl Fn(&Path) -> bool,
+) -> io::Result<usize> {
+    let mime_type = mime_type(path);
+    let content_type = content_type(mime_type);
+    let mut headers = Headers::new();
+    headers.append("Cache-Control", "max-age=3600");
+    headers.append("Accept-Ranges", "bytes");
+
+    let sidecars = sidecars(path, visible);
+    if !sidecars.is_empty() {
+        headers.append("Vary", "Accept-Encoding");
+    }
+    let (f, encoding) = match preferred_sidecar(exchange, &sidecars) {
+        Some((coding, sidecar)) => (File::open(sidecar)?, Some(coding)),
+        None => (File::open(path)?, None),
+    };
+    let md = f.metadata()?;
+    let len = md.len();
+    let mut validators = Validators::from_metadata(&md);
+    let coding = match encoding {
+        Some(_) => None,
+        None => response_coding(exchange, &mut headers, mime_type, Some(len)),
+    };
+    if let Some(name) = encoding.or(coding.map(|coding| coding.name())) {
+        validators = validators.encoded(name);
+    }
+    validators.append_to(&mut headers);
+    match evaluate(exchange.headers, &validators) {
+        Precondition::Proceed => {}
+        Precondition::NotModified => return not_modified(stream, exchange, &headers),
+        Precondition::Failed => {
+            return send_text(stream, exchange, 412, headers, "Precondition Failed\n");
+        }
+    }
+    if let Some(coding) = coding {
+        headers.append("Content-Type", &content_type);
+        return send_streamed(stream, exchange, headers, Some(coding), |body| {
+            body.write_file(f)
+        });
+    }
+    if let Some(encoding) = encoding {
+        headers.append("Content-Encoding", encoding);
+    }
+    match requested_ranges(exchange, &validators, len) {
+        Ranges::Ignore => {}
+        Ranges::Unsatisfiable => {
+            headers.append("Content-Range", &format!("bytes */{len}"));
+            return send_text(stream, exchange, 416, headers, "Range Not Satisfiable\n");
+        }
+        Ranges::Satisfiable(ranges) => {
+            return send_ranges(stream, exchange, f, headers, &content_type, len, &ranges);
+        }
+    }
+
+    headers.append("Content-Type", &content_type);
+    headers.append("Content-Length", &len.to_string());
+    write_head(stream, 200, &headers, exchange)?;
+    if exchange.head {
+        return Ok(0);
     }
-    stream.write_all(format!("Content-Length: {}\r\n\r\n", md.len()).as_bytes())?;
     stream.write_file(f)
 }
 
+/// Content codings of precompressed files and their extensions, in order of
+/// preference.
+const SIDECARS: [(&str, &str); 3] = [("br", "br"), ("zstd", "zst"), ("gzip", "gz")];
+
+/// Precompressed variants of `path` by content coding, such as `app.js.br`
+/// next to `app.js`. Symlinks are ignored, as they haven't been checked
+/// against the symlink policy like `path` has, and so are variants that
+/// aren't `visible`, e.g. because `--deny` hides `*.br`.
+fn sidecars(path: &Path, visible: impl Fn(&Path) -> bool) -> Vec<(&'static str, PathBuf)> {
+    SIDECARS
+        .iter()
+        .filter_map(|&(coding, ext)| {
+            let mut sidecar = path.as_os_str().to_owned();
+            sidecar.push(".");
+            sidecar.push(ext);
+            let sidecar = PathBuf::from(sidecar);
+            (sidecar.is_file() && !is_symlink(&sidecar) && visible(&sidecar))
+                .then_some((coding, sidecar))
+        })
+        .collect()
+}
+
+/// The sidecar in the coding the client prefers, if it accepts any.
+fn preferred_sidecar<'a>(
+    exchange: &Exchange,
+    sidecars: &'a [(&'static str, PathBuf)],
+) -> Option<(&'static str, &'a Path)> {
+    let accept_encoding = exchange.headers.get_joined("Accept-Encoding")?;
+    let codings: Vec<&str> = sidecars.iter().map(|(coding, _)| *coding).collect();
+    let coding = preferred(&accept_encoding, &codings)?;
+    sidecars
+        .iter()
+        .find(|(c, _)| *c == coding)
+        .map(|(coding, sidecar)| (*coding, sidecar.as_path()))
+}
+
+/// Bodies smaller than this are sent uncompressed, as the gain wouldn't
+/// make up for the overhead.
+const MIN_COMPRESS_SIZE: u64 = 256;
+
+/// Content coding to compress a body of `len` bytes, if known, of type
+/// `mime_type` with, if any. Adds `Vary` to the response headers if the
+/// choice depends on the request's `Accept-Encoding`.
+///
+/// Range requests are answered from the uncompressed file, and compressed
+/// bodies are sent in chunks since their length isn't known up front.
+fn response_coding(
+    exchange: &Exchange,
+    headers: &mut Headers,
+    mime_type: &str,
+    len: Option<u64>,
+) -> Option<Coding> {
+    if !exchange.compress || !compressible(mime_type) {
+        return None;
+    }
+    if !headers.contains("Vary") {
+        headers.append("Vary", "Accept-Encoding");
+    }
+    if !exchange.chunked
+        || len.is_some_and(|len| len < MIN_COMPRESS_SIZE)
+        || exchange.headers.contains("Range")
+    {
+        return None;
+    }
+    exchange
+        .headers
+        .get_joined("Accept-Encoding")
+        .and_then(|value| negotiate(&value))
+}
+
+/// Sends 200 with a body of unknown length, produced by `write_body`. The
+/// body is sent in chunks, compressed with `coding` if any, and followed by
+/// a `Server-Timing` trailer telling how long it took.
+fn send_streamed<W, F>(
+    stream: &mut W,
+    exchange: &Exchange,
+    mut headers: Headers,
+    coding: Option<Coding>,
+    write_body: F,
+) -> io::Result<usize>
+where
+    W: Write,
+    F: FnOnce(&mut dyn Write) -> io::Result<usize>,
+{
+    let started = Instant::now();
+    if let Some(coding) = coding {
+        headers.append("Content-Encoding", coding.name());
+    }
+    headers.append("Transfer-Encoding", "chunked");
+    headers.append("Trailer", "Server-Timing");
+    write_head(stream, 200, &headers, exchange)?;
+    if exchange.head {
+        return Ok(0);
+    }
+
+    let mut chunks = BufWriter::new(ChunkedWriter::new(&mut *stream));
+    let written = match coding {
+        Some(coding) => {
+            let mut encoder = Encoder::new(&mut chunks, coding)?;
+            let written = write_body(&mut encoder)?;
+            encoder.finish()?;
+            written
+        }
+        None => write_body(&mut chunks)?,
+    };
+    let mut trailers = Headers::new();
+    let millis = started.elapsed().as_secs_f64() * 1000.0;
+    trailers.append("Server-Timing", &format!("total;dur={millis:.3}"));
+    chunks
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .finish(&trailers)?;
+    Ok(written)
+}
+
+/// Ranges requested for a representation of `len` bytes. `Range` is only
+/// honoured for GET, and only if the `If-Range` condition, if any, holds.
+fn requested_ranges(exchange: &Exchange, validators: &Validators, len: u64) -> Ranges {
+    let if_range_holds = exchange
+        .headers
+        .get("If-Range")
+        .is_none_or(|value| if_range(value, validators));
+    match exchange.headers.get("Range") {
+        Some(value) if !exchange.head && if_range_holds => parse_range(value, len),
+        _ => Ranges::Ignore,
+    }
+}
+
+/// Sends 304 Not Modified, which never has a body.
+fn not_modified<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    headers: &Headers,
+) -> io::Result<usize> {
+    write_head(stream, 304, headers, exchange)?;
+    Ok(0)
+}
+
+/// Sends 206 Partial Content. A single range is sent as is, several ranges
+/// as `multipart/byteranges` body.
+fn send_ranges<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    mut file: File,
+    mut headers: Headers,
+    content_type: &str,
+    len: u64,
+    ranges: &[Range<u64>],
+) -> io::Result<usize> {
+    if let [range] = ranges {
+        headers.append("Content-Type", content_type);
+        headers.append(
+            "Content-Range",
+            &format!("bytes {}-{}/{len}", range.start, range.end - 1),
+        );
+        headers.append("Content-Length", &(range.end - range.start).to_string());
+        write_head(stream, 206, &headers, exchange)?;
+        file.seek(SeekFrom::Start(range.start))?;
+        return stream.write_file(file.take(range.end - range.start));
+    }
+
+    let boundary = boundary();
+    let part_heads: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {}-{}/{len}\r\n\r\n",
+                range.start,
+                range.end - 1
+            )
+        })
+        .collect();
+    let closing = format!("\r\n--{boundary}--\r\n");
+    let content_length = part_heads.iter().map(|h| h.len() as u64).sum::<u64>()
+        + ranges.iter().map(|r| r.end - r.start).sum::<u64>()
+        + closing.len() as u64;
+
+    headers.append(
+        "Content-Type",
+        &format!("multipart/byteranges; boundary={boundary}"),
+    );
+    headers.append("Content-Length", &content_length.to_string());
+    write_head(stream, 206, &headers, exchange)?;
+
+    let mut written = 0;
+    for (head, range) in part_heads.iter().zip(ranges) {
+        stream.write_all(head.as_bytes())?;
+        file.seek(SeekFrom::Start(range.start))?;
+        written += head.len() + stream.write_file((&mut file).take(range.end - range.start))?;
+    }
+    stream.write_all(closing.as_bytes())?;
+    Ok(written + closing.len())
+}
+
+/// Separator for multipart bodies, unlikely to appear in any of the parts.
+fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    format!("rup-{nanos:x}")
+}
+
 fn css() -> &'static str {
     "<style>body { font-size: 1.2rem; line-height: 1.2; margin: 1rem; }</style>"
 }
 
+/// Form posting files to the directory being listed. Files dropped anywhere
+/// on the page are posted right away.
+fn upload_form() -> &'static str {
+    concat!(
+        "<form id=\"upload\" method=\"post\" enctype=\"multipart/form-data\">",
+        "<input type=\"file\" name=\"file\" multiple required> ",
+        "<button>Upload</button> or drop files here</form>",
+        "<script>",
+        "const form = document.getElementById('upload');",
+        "document.addEventListener('dragover', e => e.preventDefault());",
+        "document.addEventListener('drop', e => {",
+        "e.preventDefault(); form.file.files = e.dataTransfer.files; form.submit();",
+        "});",
+        "</script>"
+    )
+}
+
 fn files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
     let mut files = vec![];
 
@@ -144,91 +784,467 @@ fn files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
     Ok(files)
 }
 
-pub(crate) fn show_dir(stream: &mut TcpStream, base: &str, path: &Path) -> io::Result<usize> {
+pub(crate) fn show_dir<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    base: &Path,
+    path: &Path,
+    visibility: &Visibility,
+    upload: bool,
+) -> io::Result<usize> {
+    let mut validators = Validators::listing(&fs::metadata(path)?);
+    let mut headers = Headers::new();
+    let coding = response_coding(exchange, &mut headers, "text/html", None);
+    if let Some(coding) = coding {
+        validators = validators.encoded(coding.name());
+    }
+    validators.append_to(&mut headers);
+    match evaluate(exchange.headers, &validators) {
+        Precondition::Proceed => {}
+        Precondition::NotModified => return not_modified(stream, exchange, &headers),
+        Precondition::Failed => {
+            return send_text(stream, exchange, 412, headers, "Precondition Failed\n");
+        }
+    }
+    headers.append("Content-Type", "text/html; charset=utf-8");
+    if exchange.chunked {
+        return send_streamed(stream, exchange, headers, coding, |body| {
+            write_listing(body, base, path, visibility, upload)
+        });
+    }
+
+    // without chunked transfer the length must be known up front
     let mut buf: Vec<u8> = Vec::new();
-    buf.write_all(
-        format!(
-            "<html><head>{}</head><body><p style=\"color: #fff; background-color: #44f;\">Path: {}</p><ol>",
-            css(),
-            &path.to_str().unwrap()[1..]
-        )
-        .as_bytes(),
-    )?;
+    write_listing(&mut buf, base, path, visibility, upload)?;
+    headers.append("Content-Length", &buf.len().to_string());
+    write_head(stream, 200, &headers, exchange)?;
+    if exchange.head {
+        return Ok(0);
+    }
+    stream.write_all(&buf)?;
+
+    Ok(buf.len())
+}
 
-    if base != path.to_str().unwrap() {
-        buf.write_all("<li><a href=\"..\">..</a></li>".as_bytes())?;
+/// Writes the HTML page listing the visible entries of directory `path`,
+/// followed by the upload form if `upload`.
+fn write_listing(
+    out: &mut dyn Write,
+    base: &Path,
+    path: &Path,
+    visibility: &Visibility,
+    upload: bool,
+) -> io::Result<usize> {
+    let mut written = 0;
+    let mut write = |s: &str| {
+        written += s.len();
+        out.write_all(s.as_bytes())
+    };
+    write(&format!(
+        "<html><head>{}</head><body><p style=\"color: #fff; background-color: #44f;\">Path: {}</p><ol>",
+        css(),
+        escape(&path.to_string_lossy()[1..])
+    ))?;
+
+    if base != path {
+        write("<li><a href=\"..\">..</a></li>")?;
     }
 
     let paths = files_in(path)?;
     for f in paths {
         if let (Ok(href), Some(name)) = (f.strip_prefix(base), f.file_name()) {
-            let href = href.to_str().unwrap();
-            let name = name.to_str().unwrap();
-            buf.write_all(
-                format!(
-                    "<li><a href=\"/{href}\">{name}{}</li>",
-                    if f.is_dir() { "/" } else { "" }
-                )
-                .as_bytes(),
-            )?;
-        }
-    }
-    buf.write_all(b"</ol></body><html>")?;
-
-    stream.write_all(b"HTTP/1.1 200 OK\n")?;
-    stream.write_all(b"Content-Type: text/html; charset=utf-8\n")?;
-    stream.write_all(format!("Content-Length: {}\r\n\r\n", buf.len()).as_bytes())?;
-    stream.write_all(&buf)?;
+            if !visibility.allows(base, &f) {
+                continue;
+            }
+            let href = encode_path(&href.to_string_lossy());
+            let name = escape(&name.to_string_lossy());
+            let suffix = if is_symlink(&f) {
+                "@"
+            } else if f.is_dir() {
+                "/"
+            } else {
+                ""
+            };
+            write(&format!("<li><a href=\"/{href}\">{name}{suffix}</a></li>"))?;
+        }
+    }
+    write("</ol>")?;
+    if upload {
+        write(upload_form())?;
+    }
+    write("</body><html>")?;
+    Ok(written)
+}
 
-    Ok(buf.len())
+/// Sends 201 Created or 204 No Content after a file or directory has been
+/// written, with the validators of the new content if it is a file.
+fn send_stored<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    path: &Path,
+    created: bool,
+) -> io::Result<usize> {
+    let mut headers = Headers::new();
+    let md = fs::metadata(path)?;
+    if md.is_file() {
+        Validators::from_metadata(&md).append_to(&mut headers);
+    }
+    if created {
+        send_text(stream, exchange, 201, headers, "Created\n")
+    } else {
+        write_head(stream, 204, &headers, exchange)?;
+        Ok(0)
+    }
+}
+
+/// Answers OPTIONS. WebDAV clients look for the `DAV` header to tell which
+/// class of WebDAV is served.
+fn send_options<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    allow: &str,
+    dav: bool,
+) -> io::Result<usize> {
+    let mut headers = Headers::new();
+    headers.append("Allow", allow);
+    if dav {
+        headers.append("DAV", "1");
+        // makes Windows use WebDAV rather than FrontPage extensions
+        headers.append("MS-Author-Via", "DAV");
+    }
+    headers.append("Content-Length", "0");
+    write_head(stream, 200, &headers, exchange)?;
+    Ok(0)
 }
 
-pub(crate) fn http_400(stream: &mut TcpStream, reason: &str) -> io::Result<usize> {
-    let body_string = format!("Bad Request: {reason}\n");
-    let body = body_string.as_bytes();
-    stream.write_all(b"HTTP/1.1 400 Bad Request\n")?;
-    stream.write_all(b"Content-Type: text/plain\n")?;
-    stream.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())?;
-    stream.write_all(body)?;
-    Err(io::Error::other(format!(
-        "{}: {}",
-        "400 Bad Request".red(),
-        reason
-    )))
+fn send_xml<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    code: u16,
+    body: &str,
+) -> io::Result<usize> {
+    let mut headers = Headers::new();
+    headers.append("Content-Type", "application/xml; charset=utf-8");
+    headers.append("Content-Length", &body.len().to_string());
+    write_head(stream, code, &headers, exchange)?;
+    if exchange.head {
+        return Ok(0);
+    }
+    stream.write_all(body.as_bytes())?;
+    Ok(body.len())
 }
 
-pub(crate) fn http_404(stream: &mut TcpStream, reason: &str) -> io::Result<usize> {
-    stream.write_all(b"HTTP/1.1 404 Not Found\n")?;
-    stream.write_all(b"Content-Type: text/plain\n")?;
+/// Sends the client to `location`, with a short body for clients that don't
+/// follow redirects.
+fn send_redirect<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    code: u16,
+    location: &str,
+) -> io::Result<usize> {
+    let mut headers = Headers::new();
+    headers.append("Location", location);
+    let body = format!("{}: {location}\n", reason_phrase(code));
+    send_text(stream, exchange, code, headers, &body)
+}
+
+/// Writes a plain text body for error responses.
+fn send_text<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    code: u16,
+    mut headers: Headers,
+    body: &str,
+) -> io::Result<usize> {
+    headers.append("Content-Type", "text/plain");
+    headers.append("Content-Length", &body.len().to_string());
+    write_head(stream, code, &headers, exchange)?;
+    if exchange.head {
+        return Ok(0);
+    }
+    stream.write_all(body.as_bytes())?;
+    Ok(body.len())
+}
+
+pub(crate) fn http_400<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    reason: &str,
+) -> io::Result<usize> {
+    eprintln!("{}: {}", "400 Bad Request".red(), reason);
+    let body = format!("Bad Request: {reason}\n");
+    send_text(stream, exchange, 400, Headers::new(), &body)
+}
+
+pub(crate) fn http_401<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    realm: &str,
+) -> io::Result<usize> {
+    eprintln!(
+        "{}: Credentials required for {realm}",
+        "401 Unauthorized".red()
+    );
+    let mut headers = Headers::new();
+    headers.append(
+        "WWW-Authenticate",
+        &format!("Basic realm=\"{realm}\", charset=\"UTF-8\""),
+    );
+    send_text(stream, exchange, 401, headers, "Unauthorized\n")
+}
+
+pub(crate) fn http_403<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    reason: &str,
+) -> io::Result<usize> {
+    eprintln!("{}: {}", "403 Forbidden".red(), reason);
+    let body = format!("Forbidden: {reason}\n");
+    send_text(stream, exchange, 403, Headers::new(), &body)
+}
+
+pub(crate) fn http_404<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    reason: &str,
+) -> io::Result<usize> {
+    eprintln!("{}: {}", "404 Not Found".red(), reason);
 
     let path_404 = Path::new("./404.html");
     if path_404.exists() {
         let file_404 = File::open(path_404)?;
         let content_length = &file_404.metadata()?.len();
-        stream.write_all(b"Content-Type: text/html\n")?;
-        stream.write_all(format!("Content-Length: {content_length}\r\n\r\n").as_bytes())?;
-        stream.write_file(file_404)?;
+        let mut headers = Headers::new();
+        headers.append("Content-Type", "text/html");
+        headers.append("Content-Length", &content_length.to_string());
+        write_head(stream, 404, &headers, exchange)?;
+        if exchange.head {
+            return Ok(0);
+        }
+        stream.write_file(file_404)
     } else {
-        let body_string = format!("Not Found: {reason}\n");
-        let body = body_string.as_bytes();
-        stream.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())?;
-        stream.write_all(body)?;
-    }
-
-    Err(io::Error::other(format!(
-        "{}: {}",
-        "404 Not Found".red(),
-        reason
-    )))
-}
-
-pub(crate) fn http_405(stream: &mut TcpStream) -> io::Result<usize> {
-    let body_string = "405 Method Not Allowed\n";
-    let body = body_string.as_bytes();
-    stream.write_all(b"HTTP/1.1 405 Method Not Allowed\n")?;
-    stream.write_all(b"Allow: GET\n")?;
-    stream.write_all(b"Content-Type: text/plain\n")?;
-    stream.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())?;
-    stream.write_all(body)?;
-    Err(io::Error::other(body_string))
+        let body = format!("Not Found: {reason}\n");
+        send_text(stream, exchange, 404, Headers::new(), &body)
+    }
+}
+
+pub(crate) fn http_405<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    allow: &str,
+) -> io::Result<usize> {
+    let body = "405 Method Not Allowed\n";
+    eprint!("{body}");
+    let mut headers = Headers::new();
+    headers.append("Allow", allow);
+    send_text(stream, exchange, 405, headers, body)
+}
+
+/// Sends an error response whose body is just the reason.
+pub(crate) fn http_error<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    code: u16,
+    reason: &str,
+) -> io::Result<usize> {
+    let status = format!("{code} {}", reason_phrase(code));
+    eprintln!("{}: {}", status.red(), reason);
+    let body = format!("{}: {reason}\n", reason_phrase(code));
+    send_text(stream, exchange, code, Headers::new(), &body)
+}
+
+pub(crate) fn http_429<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    retry_after: u64,
+) -> io::Result<usize> {
+    eprintln!(
+        "{}: Retry after {retry_after}s",
+        "429 Too Many Requests".red()
+    );
+    let body = format!("Too Many Requests: Retry after {retry_after} seconds\n");
+    let mut headers = Headers::new();
+    headers.append("Retry-After", &retry_after.to_string());
+    send_text(stream, exchange, 429, headers, &body)
+}
+
+/// Seconds clients are asked to wait before retrying a request turned away
+/// with 503.
+const RETRY_AFTER: u64 = 1;
+
+pub(crate) fn http_503<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    reason: &str,
+) -> io::Result<usize> {
+    eprintln!("{}: {}", "503 Service Unavailable".red(), reason);
+    let body = format!("Service Unavailable: {reason}\n");
+    let mut headers = Headers::new();
+    headers.append("Retry-After", &RETRY_AFTER.to_string());
+    send_text(stream, exchange, 503, headers, &body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        ("GET / HTTP/1.1\r\n", "GET", "/", None, "HTTP/1.1"),
+        ("GET /a%20b?x=1&y=%20 HTTP/1.1\r\n", "GET", "/a b", Some("x=1&y=%20"), "HTTP/1.1"),
+        ("HEAD /index.html#top HTTP/1.0\r\n", "HEAD", "/index.html", None, "HTTP/1.0"),
+        ("GET /search?q=rust#results\r\n", "GET", "/search", Some("q=rust"), "HTTP/1.0"),
+    )]
+    fn test_request_line(line: &str, method: &str, path: &str, query: Option<&str>, version: &str) {
+        let request = Request::try_from(line.to_string()).unwrap();
+        assert_eq!(request.method, method);
+        assert_eq!(request.path, path);
+        assert_eq!(request.query.as_deref(), query);
+        assert_eq!(request.version, version);
+    }
+
+    fn get(input: &str) -> Result<Request, RequestError> {
+        Request::get(&mut input.as_bytes())
+    }
+
+    #[test]
+    fn test_get() {
+        let input = "\r\nGET /a HTTP/1.1\r\nHost: localhost\r\nX-A: 1\r\n\r\nGET /b HTTP/1.1\r\n";
+        let mut reader = input.as_bytes();
+        let request = Request::get(&mut reader).unwrap();
+        assert_eq!(request.path, "/a");
+        assert_eq!(request.headers.get("x-a"), Some("1"));
+        assert_eq!(reader, b"GET /b HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn test_get_limits() {
+        let long_path = "a".repeat(MAX_REQUEST_LINE);
+        let input = format!("GET /{long_path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!(get(&input).unwrap_err(), RequestError::UriTooLong);
+
+        let long_value = "a".repeat(MAX_HEADER_SIZE);
+        let input = format!("GET / HTTP/1.1\r\nHost: localhost\r\nX-A: {long_value}\r\n\r\n");
+        assert_eq!(get(&input).unwrap_err(), RequestError::HeadersTooLarge);
+
+        let field = format!("X-A: {}\r\n", "a".repeat(1000));
+        let input = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            field.repeat(MAX_HEADER_SIZE / 1000 + 1)
+        );
+        assert_eq!(get(&input).unwrap_err(), RequestError::HeadersTooLarge);
+    }
+
+    #[p_test(
+        (""),
+        ("\r\n\r\n"),
+    )]
+    fn test_get_empty(input: &str) {
+        assert!(get(input).unwrap().method.is_empty());
+    }
+
+    #[p_test(
+        ("GET / HTTP/1.1\r\n\r\n"),
+        ("GET / HTTP/1.1\r\nHost: localhost\r\n"),
+        ("GET / HTTP/1.1\r\nHost localhost\r\n\r\n"),
+    )]
+    fn test_get_malformed(input: &str) {
+        assert!(matches!(get(input), Err(RequestError::Malformed(_))));
+    }
+
+    #[p_test(
+        ("GET"),
+        ("GET / HTTP/1.1 extra\r\n"),
+        ("GET / FTP/1.0\r\n"),
+        ("GET /%zz HTTP/1.1\r\n"),
+    )]
+    fn test_invalid_request_line(line: &str) {
+        assert!(Request::try_from(line.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_sidecar() {
+        let dir = std::env::temp_dir().join(format!("rup-sidecar-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.js");
+        fs::write(&path, "console.log(1)").unwrap();
+        fs::write(dir.join("app.js.gz"), "gzipped").unwrap();
+        fs::write(dir.join("app.js.br"), "brotli").unwrap();
+
+        let visible = Visibility::default();
+        let response = |accept_encoding: &str, visibility: &Visibility| {
+            let mut headers = Headers::new();
+            headers.append("Accept-Encoding", accept_encoding);
+            let exchange = Exchange {
+                headers: &headers,
+                keep_alive: None,
+                head: false,
+                compress: true,
+                chunked: true,
+                hsts: None,
+            };
+            let mut out = vec![];
+            Response::file(&dir, &path, visibility)
+                .send_to(&mut out, &exchange)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let br = response("gzip, br", &visible);
+        assert!(br.contains("Content-Encoding: br\r\n"));
+        assert!(br.contains("Content-Type: application/javascript\r\n"));
+        assert!(br.contains("Vary: Accept-Encoding\r\n"));
+        assert!(br.ends_with("\r\n\r\nbrotli"));
+        assert!(response("gzip", &visible).ends_with("\r\n\r\ngzipped"));
+        let no_br = Visibility {
+            allow: vec![],
+            deny: vec!["*.br".to_string()],
+        };
+        assert!(response("gzip, br", &no_br).ends_with("\r\n\r\ngzipped"));
+        let identity = response("identity", &visible);
+        assert!(!identity.contains("Content-Encoding"));
+        assert!(identity.contains("Vary: Accept-Encoding\r\n"));
+        assert!(identity.ends_with("\r\n\r\nconsole.log(1)"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_listing_escapes_names() {
+        let dir = std::env::temp_dir().join(format!("rup-listing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("<img src=x onerror=alert(1)>.txt"), "").unwrap();
+        fs::write(dir.join("a\"b.txt"), "").unwrap();
+
+        let mut out = vec![];
+        let base = dir.parent().unwrap();
+        write_listing(&mut out, base, &dir, &Visibility::default(), false).unwrap();
+        let listing = String::from_utf8(out).unwrap();
+        assert!(!listing.contains("<img"));
+        assert!(listing.contains(">&lt;img src=x onerror=alert(1)&gt;.txt</a>"));
+        assert!(listing.contains("%3Cimg%20src=x%20onerror=alert(1)%3E.txt\">"));
+        assert!(listing.contains("/a%22b.txt\">a&quot;b.txt</a>"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_redirect() {
+        let headers = Headers::new();
+        let exchange = Exchange {
+            headers: &headers,
+            keep_alive: None,
+            head: false,
+            compress: false,
+            chunked: true,
+            hsts: Some(86400),
+        };
+        let mut out = vec![];
+        Response::redirect(308, "https://localhost:3000/a")
+            .send_to(&mut out, &exchange)
+            .unwrap();
+        let response = String::from_utf8(out).unwrap();
+        assert!(response.starts_with("HTTP/1.1 308 Permanent Redirect\r\n"));
+        assert!(response.contains("Location: https://localhost:3000/a\r\n"));
+        assert!(response.contains("Strict-Transport-Security: max-age=86400\r\n"));
+    }
 }
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    net::IpAddr,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Clients remembered before those that are idle and within their limits
+/// are forgotten.
+const MAX_CLIENTS: usize = 4096;
+/// Smallest and largest pieces of a response written at once when its rate
+/// is limited, so that it flows steadily rather than in bursts.
+const MIN_CHUNK: u64 = 512;
+const MAX_CHUNK: u64 = 64 * 1024;
+
+/// Limits applied to each client address. Every limit is optional.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Limits {
+    /// Requests per second, in bursts of up to a second's worth.
+    pub requests: Option<NonZeroU32>,
+    /// Response bytes per second, shared by all connections of a client.
+    pub bytes: Option<NonZeroU64>,
+    /// Connections open at the same time, including queued ones.
+    pub connections: Option<NonZeroUsize>,
+}
+
+/// Tokens refilled at a steady `rate` per second, up to `capacity`.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn full(rate: f64, now: Instant) -> Self {
+        TokenBucket {
+            rate,
+            capacity: rate,
+            tokens: rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+
+    /// Takes `n` tokens if there are as many, or returns how long until
+    /// there will be.
+    fn try_take(&mut self, n: f64, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= n {
+            self.tokens -= n;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((n - self.tokens) / self.rate))
+        }
+    }
+
+    /// Takes `n` tokens even if there aren't as many, returning how long
+    /// until the debt is paid back.
+    fn take(&mut self, n: f64, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= n;
+        Duration::from_secs_f64((-self.tokens).max(0.0) / self.rate)
+    }
+}
+
+#[derive(Debug)]
+struct Client {
+    requests: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    connections: usize,
+}
+
+impl Client {
+    /// Whether forgetting the client would lift none of its limits.
+    fn is_idle(&mut self, now: Instant) -> bool {
+        self.connections == 0
+            && self
+                .requests
+                .as_mut()
+                .is_none_or(|bucket| bucket.is_full(now))
+            && self.bytes.as_mut().is_none_or(|bucket| bucket.is_full(now))
+    }
+}
+
+/// Keeps track of what each client address is using, so that one client
+/// can't starve the others.
+pub(crate) struct Limiter {
+    requests: Option<f64>,
+    bytes: Option<f64>,
+    connections: Option<usize>,
+    clients: Mutex<HashMap<IpAddr, Client>>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Limiter {
+            requests: limits.requests.map(|n| n.get().into()),
+            bytes: limits.bytes.map(|n| n.get() as f64),
+            connections: limits.connections.map(NonZeroUsize::get),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.requests.is_some() || self.bytes.is_some() || self.connections.is_some()
+    }
+
+    /// Counts a new connection from `ip`, or returns `None` if the client
+    /// already has as many as it may.
+    pub(crate) fn connect(self: &Arc<Self>, ip: IpAddr) -> Option<Connection> {
+        if self.is_enabled() {
+            let now = Instant::now();
+            let mut clients = self.clients.lock().unwrap();
+            if clients.len() >= MAX_CLIENTS {
+                clients.retain(|_, client| !client.is_idle(now));
+            }
+            let client = clients.entry(ip).or_insert_with(|| Client {
+                requests: self.requests.map(|rate| TokenBucket::full(rate, now)),
+                bytes: self.bytes.map(|rate| TokenBucket::full(rate, now)),
+                connections: 0,
+            });
+            if self
+                .connections
+                .is_some_and(|max| client.connections >= max)
+            {
+                return None;
+            }
+            client.connections += 1;
+        }
+        Some(Connection {
+            limiter: Arc::clone(self),
+            ip,
+        })
+    }
+
+    fn with_client<T>(&self, ip: IpAddr, f: impl FnOnce(&mut Client) -> T) -> Option<T> {
+        self.clients.lock().unwrap().get_mut(&ip).map(f)
+    }
+}
+
+/// A connection counted against the limits of its client, until dropped.
+pub(crate) struct Connection {
+    limiter: Arc<Limiter>,
+    ip: IpAddr,
+}
+
+impl Connection {
+    /// Counts a request, or returns how many seconds the client should wait
+    /// before sending it again.
+    pub(crate) fn request(&self) -> Result<(), u64> {
+        if self.limiter.requests.is_none() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        self.limiter
+            .with_client(self.ip, |client| match &mut client.requests {
+                Some(bucket) => bucket.try_take(1.0, now),
+                None => Ok(()),
+            })
+            .unwrap_or(Ok(()))
+            .map_err(|wait| wait.as_secs_f64().ceil() as u64)
+    }
+
+    /// Counts `n` bytes sent, returning how long to wait before sending
+    /// them to stay within the rate.
+    fn send(&self, n: usize) -> Duration {
+        if self.limiter.bytes.is_none() {
+            return Duration::ZERO;
+        }
+        let now = Instant::now();
+        self.limiter
+            .with_client(self.ip, |client| match &mut client.bytes {
+                Some(bucket) => bucket.take(n as f64, now),
+                None => Duration::ZERO,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Largest piece of a response to write at once: about a tenth of a
+    /// second's worth.
+    fn chunk_size(&self) -> usize {
+        match self.limiter.bytes {
+            Some(rate) => ((rate / 10.0) as u64).clamp(MIN_CHUNK, MAX_CHUNK) as usize,
+            None => usize::MAX,
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.limiter.is_enabled() {
+            self.limiter
+                .with_client(self.ip, |client| client.connections -= 1);
+        }
+    }
+}
+
+/// Writes to `inner` no faster than the byte rate of the connection's
+/// client.
+pub(crate) struct Paced<'a, W: Write> {
+    inner: W,
+    connection: &'a Connection,
+}
+
+impl<'a, W: Write> Paced<'a, W> {
+    pub(crate) fn new(inner: W, connection: &'a Connection) -> Self {
+        Paced { inner, connection }
+    }
+}
+
+impl<W: Write> Write for Paced<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let buf = &buf[..buf.len().min(self.connection.chunk_size())];
+        let wait = self.connection.send(buf.len());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        self.inner.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limiter(requests: u32, bytes: u64, connections: usize) -> Arc<Limiter> {
+        Arc::new(Limiter::new(&Limits {
+            requests: NonZeroU32::new(requests),
+            bytes: NonZeroU64::new(bytes),
+            connections: NonZeroUsize::new(connections),
+        }))
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(2.0, start);
+        assert_eq!(bucket.try_take(1.0, start), Ok(()));
+        assert_eq!(bucket.try_take(1.0, start), Ok(()));
+        assert_eq!(bucket.try_take(1.0, start), Err(Duration::from_millis(500)));
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.try_take(1.0, later), Ok(()));
+        // never more than the capacity
+        let much_later = later + Duration::from_secs(60);
+        assert!(bucket.is_full(much_later));
+        assert_eq!(bucket.take(4.0, much_later), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_connections() {
+        let limiter = limiter(0, 0, 2);
+        let ip = "10.0.0.1".parse().unwrap();
+        let first = limiter.connect(ip).unwrap();
+        let second = limiter.connect(ip).unwrap();
+        assert!(limiter.connect(ip).is_none());
+        assert!(limiter.connect("10.0.0.2".parse().unwrap()).is_some());
+        drop(first);
+        assert!(limiter.connect(ip).is_some());
+        drop(second);
+        let clients = limiter.clients.lock().unwrap();
+        assert_eq!(clients[&ip].connections, 0);
+    }
+
+    #[test]
+    fn test_requests() {
+        let limiter = limiter(3, 0, 0);
+        let connection = limiter.connect("::1".parse().unwrap()).unwrap();
+        for _ in 0..3 {
+            assert_eq!(connection.request(), Ok(()));
+        }
+        assert_eq!(connection.request(), Err(1));
+        // other clients have their own budget
+        let other = limiter.connect("::2".parse().unwrap()).unwrap();
+        assert_eq!(other.request(), Ok(()));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let limiter = limiter(0, 0, 0);
+        let ip = "10.0.0.1".parse().unwrap();
+        let connections: Vec<_> = (0..100).map(|_| limiter.connect(ip).unwrap()).collect();
+        assert_eq!(connections[0].request(), Ok(()));
+        assert_eq!(connections[0].send(1 << 30), Duration::ZERO);
+        assert!(limiter.clients.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_paced() {
+        let limiter = limiter(0, 10_000, 0);
+        let connection = limiter.connect("10.0.0.1".parse().unwrap()).unwrap();
+        let mut out = vec![];
+        let start = Instant::now();
+        // a second's worth goes out at once, the rest at the rate
+        Paced::new(&mut out, &connection)
+            .write_all(&[0; 12_000])
+            .unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(out.len(), 12_000);
+        assert!(elapsed >= Duration::from_millis(190), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+}
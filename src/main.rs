@@ -1,98 +1,710 @@
+mod access;
+mod auth;
+mod base64;
+mod body;
+mod cert;
+mod chunked;
 mod cli;
+mod compress;
+mod conditional;
+mod crypt;
+mod date;
 mod decode;
+mod deflate;
+mod glob;
+mod headers;
 mod http;
+mod limit;
+mod manage;
 mod mime;
+mod multipart;
+mod pool;
+mod range;
+mod redirect;
+mod resolve;
+mod sha;
+mod throttle;
+mod timeout;
+mod tls;
+mod upload;
+mod visibility;
+mod webdav;
+mod xml;
 
 use crate::{
+    access::IpAction,
+    auth::Auth,
+    body::Body,
     cli::Args,
-    http::{Request, Response},
+    decode::{decode_percent, encode_path},
+    headers::Headers,
+    http::{Exchange, KeepAlive, Request, RequestError, Response},
+    limit::{Connection, Limiter, Paced},
+    manage::destination_path,
+    pool::Pool,
+    redirect::{https_location, redirect_code},
+    resolve::{Denied, confine, resolve, resolve_new},
+    throttle::Throttled,
+    timeout::{ClientStream, TimedStream, is_timeout},
+    tls::Tls,
 };
 use colorust::Color;
 use std::{
-    env, io,
-    net::{TcpListener, TcpStream},
-    path::PathBuf,
-    process, thread,
+    env,
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    thread,
+    time::Duration,
 };
 
-fn handle_connection(mut stream: TcpStream, base: PathBuf) -> io::Result<usize> {
-    let request = match Request::get(&mut stream) {
-        Ok(request) => request,
-        Err(e) => {
-            return Response::error(400, &e).send_to(&mut stream);
+/// Serves a connection, first performing the TLS handshake if HTTPS is
+/// enabled.
+fn handle_connection(
+    stream: TcpStream,
+    connection: Connection,
+    args: Arc<Args>,
+    auth: Option<Arc<Auth>>,
+    tls: Option<Arc<Tls>>,
+) -> io::Result<()> {
+    let write_timeout = Duration::from_secs(args.write_timeout.get());
+    let mut stream = TimedStream::new(stream, write_timeout)?;
+    match tls {
+        Some(tls) => {
+            // the handshake gets as long as a request head
+            stream.set_deadline(Duration::from_secs(args.header_timeout.get()));
+            match tls.accept(stream) {
+                Ok(stream) => serve(stream, connection, &args, auth.as_deref()),
+                Err(e) if is_timeout(&e) => Ok(()),
+                Err(e) => Err(e),
+            }
+        }
+        None => serve(stream, connection, &args, auth.as_deref()),
+    }
+}
+
+/// Serves requests on a connection until the client closes it, asks to close
+/// it, stays idle longer than the keep-alive timeout, or the maximum number of
+/// requests per connection is reached. Pipelined requests are answered in the
+/// order they were received.
+fn serve<S: ClientStream>(
+    stream: S,
+    connection: Connection,
+    args: &Args,
+    auth: Option<&Auth>,
+) -> io::Result<()> {
+    let timeout = args.keep_alive_timeout.get();
+    let max_requests = args.max_requests.get();
+    let header_timeout = Duration::from_secs(args.header_timeout.get());
+    let body_timeout = Duration::from_secs(args.body_timeout.get());
+    // responses are written through their own handle, so that the request
+    // body can be read while responding
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    for served in 1..=max_requests {
+        // wait for the next request no longer than the idle timeout
+        reader.get_mut().set_deadline(Duration::from_secs(timeout));
+        match reader.fill_buf() {
+            Ok([]) => return Ok(()),
+            Ok(_) => {}
+            Err(e) if is_timeout(&e) => return Ok(()),
+            // TLS clients may close without saying so between requests
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        // then give the client a fixed time to send the whole request head
+        reader.get_mut().set_deadline(header_timeout);
+        let request = match Request::get(&mut reader) {
+            Ok(request) => request,
+            Err(e) => return reject_request(&mut writer, e),
+        };
+
+        if request.method.is_empty() && request.path.is_empty() {
+            // the client closed the connection or stayed idle for too long
+            return Ok(());
+        }
+
+        reader.get_mut().set_read_timeout(body_timeout)?;
+        let interim = match request.expects_continue() {
+            true => Some(writer.try_clone()?),
+            false => None,
+        };
+        let mut body = match Body::new(&mut reader, &request, args.max_body_size, interim) {
+            Ok(body) => body,
+            Err(e) => return reject_request(&mut writer, e),
+        };
+
+        let keep_alive = request.keep_alive() && !body.awaiting_continue() && served < max_requests;
+        let exchange = Exchange {
+            headers: &request.headers,
+            keep_alive: keep_alive.then(|| KeepAlive {
+                timeout,
+                max: max_requests - served,
+            }),
+            head: request.method == "HEAD",
+            compress: args.compress,
+            chunked: request.version == "HTTP/1.1",
+            hsts: args.tls.hsts,
+        };
+        let condition = args.throttle.condition(&request.path);
+        if let Some(condition) = condition {
+            condition.wait();
+        }
+        let bandwidth = condition.and_then(|condition| condition.bandwidth);
+        let paced = Paced::new(&mut writer, &connection);
+        let mut stream = BufWriter::new(Throttled::new(paced, bandwidth));
+        match connection.request() {
+            Ok(()) => respond(&mut stream, &exchange, &request, &mut body, args, auth)?,
+            Err(retry_after) => {
+                Response::too_many_requests(retry_after).send_to(&mut stream, &exchange)?
+            }
+        };
+        stream.flush()?;
+
+        // whatever the response didn't read of the body is skipped
+        if !keep_alive || !body.drain() {
+            break;
         }
+    }
+    Ok(())
+}
+
+/// Answers a request that can't be read or served with an error, after which
+/// the connection is closed.
+fn reject_request<W: Write>(writer: &mut W, e: RequestError) -> io::Result<()> {
+    let exchange = Exchange {
+        headers: &Headers::new(),
+        keep_alive: None,
+        head: false,
+        compress: false,
+        chunked: false,
+        hsts: None,
     };
+    let mut stream = BufWriter::new(writer);
+    Response::error(e.code(), e.reason()).send_to(&mut stream, &exchange)?;
+    stream.flush()
+}
 
+/// Answers the request on a connection to the plain HTTP listener by sending
+/// the client to the same path and query over HTTPS, on the host it asked
+/// for. The connection is closed afterwards.
+fn redirect_connection(
+    stream: TcpStream,
+    connection: Connection,
+    args: Arc<Args>,
+) -> io::Result<()> {
+    let write_timeout = Duration::from_secs(args.write_timeout.get());
+    let mut stream = TimedStream::new(stream, write_timeout)?;
+    stream.set_deadline(Duration::from_secs(args.header_timeout.get()));
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let request = match Request::get(&mut reader) {
+        Ok(request) => request,
+        Err(e) => return reject_request(&mut writer, e),
+    };
     if request.method.is_empty() && request.path.is_empty() {
-        // ignore empty requests
-        return Ok(0);
+        return Ok(());
     }
 
-    println!("{} {}", &request.method.cyan(), &request.path.yellow());
+    let exchange = Exchange {
+        headers: &request.headers,
+        keep_alive: None,
+        head: request.method == "HEAD",
+        compress: false,
+        chunked: request.version == "HTTP/1.1",
+        hsts: None,
+    };
+    let query = request.query.as_deref();
+    let location = request
+        .headers
+        .get("Host")
+        .and_then(|host| https_location(host, args.port, &request.path, query));
+    let mut stream = BufWriter::new(&mut writer);
+    match (connection.request(), location) {
+        (Err(retry_after), _) => {
+            Response::too_many_requests(retry_after).send_to(&mut stream, &exchange)?
+        }
+        (Ok(()), Some(location)) => {
+            println!(
+                "{} {}{} {}",
+                &request.method.cyan(),
+                &request.path.yellow(),
+                query.map(|query| format!("?{query}")).unwrap_or_default(),
+                format!("-> {location}").green()
+            );
+            let code = redirect_code(&request.method);
+            Response::redirect(code, &location).send_to(&mut stream, &exchange)?
+        }
+        (Ok(()), None) => {
+            Response::error(400, "Missing or invalid Host").send_to(&mut stream, &exchange)?
+        }
+    };
+    stream.flush()
+}
 
-    if &request.method != "GET" {
-        println!(
-            "Requested Http Method: {} is not supported.",
-            &request.method
-        );
-        return Response::error(405, "Method not allowed").send_to(&mut stream);
+fn respond<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    request: &Request,
+    body: &mut R,
+    args: &Args,
+    auth: Option<&Auth>,
+) -> io::Result<usize> {
+    let query = match &request.query {
+        Some(query) => format!("?{query}"),
+        None => "".to_string(),
+    };
+    println!(
+        "{} {}{}",
+        &request.method.cyan(),
+        &request.path.yellow(),
+        query
+    );
+
+    if let Some(auth) = auth
+        && !auth.permits(request, &args.path)
+    {
+        return Response::unauthorized(auth.realm()).send_to(stream, exchange);
+    }
+
+    match request.method.as_str() {
+        "GET" | "HEAD" => {}
+        "PUT" if args.write => return store(stream, exchange, request, body, args),
+        "POST" if args.write => return post_form(stream, exchange, request, body, args),
+        "DELETE" if args.write => return remove(stream, exchange, request, args),
+        "MKCOL" if args.write => return make_dir(stream, exchange, request, args),
+        "MOVE" if args.write => return transfer(stream, exchange, request, args, false),
+        "COPY" if args.write && args.webdav => {
+            return transfer(stream, exchange, request, args, true);
+        }
+        "PROPFIND" if args.webdav => return properties(stream, exchange, request, body, args),
+        "PROPPATCH" if args.write && args.webdav => {
+            return properties(stream, exchange, request, body, args);
+        }
+        "OPTIONS" => {
+            return Response::options(allowed_methods(args), args.webdav).send_to(stream, exchange);
+        }
+        method => {
+            println!("Requested Http Method: {method} is not supported.");
+            return Response::method_not_allowed(allowed_methods(args)).send_to(stream, exchange);
+        }
     }
 
-    let mut path = base.clone();
-    if request.path != "/" {
-        path.push(&request.path[1..]);
+    let base = args.path.as_path();
+    let path = match resolve(base, &request.path, args.symlinks) {
+        Ok(path) => path,
+        Err(denied) => return deny(stream, exchange, denied),
+    };
+    if !is_visible(args, &path) {
+        return deny(stream, exchange, Denied::NotFound);
     }
 
-    if !path.exists() {
-        Response::error(404_u16, "Requested path does not exist.").send_to(&mut stream)
-    } else if path.is_dir() {
+    if path.is_dir() {
         let index = path.join("index.html");
-        if index.exists() {
-            Response::file(&index).send_to(&mut stream)
-        } else {
-            let base = base.to_str().unwrap();
-            // show_dir(&mut stream, base, path)
-            Response::directory(base, &path).send_to(&mut stream)
+        match confine(base, &index, args.symlinks) {
+            Ok(()) if is_visible(args, &index) => {
+                Response::file(base, &index, &args.visibility).send_to(stream, exchange)
+            }
+            Ok(()) | Err(Denied::NotFound) => {
+                Response::directory(base, &path, &args.visibility, args.write)
+                    .send_to(stream, exchange)
+            }
+            Err(denied) => deny(stream, exchange, denied),
         }
     } else {
-        // send_file(&mut stream, path.as_path())
-        Response::file(&path).send_to(&mut stream)
+        Response::file(base, &path, &args.visibility).send_to(stream, exchange)
+    }
+}
+
+/// Stores the body of a PUT request at the requested path, which needn't
+/// exist yet but must stay under the root.
+fn store<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    request: &Request,
+    body: &mut R,
+    args: &Args,
+) -> io::Result<usize> {
+    let path = match resolve_new(&args.path, &request.path, args.symlinks) {
+        Ok(path) => path,
+        Err(denied) => return deny(stream, exchange, denied),
+    };
+    if !is_visible(args, &path) {
+        return deny(stream, exchange, Denied::Forbidden);
     }
+    upload::put(stream, exchange, &path, body, args.create_dirs)
+}
+
+/// Saves the files posted by the upload form of a directory listing.
+fn post_form<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    request: &Request,
+    body: &mut R,
+    args: &Args,
+) -> io::Result<usize> {
+    let path = match resolve(&args.path, &request.path, args.symlinks) {
+        Ok(path) => path,
+        Err(denied) => return deny(stream, exchange, denied),
+    };
+    if !is_visible(args, &path) {
+        return deny(stream, exchange, Denied::NotFound);
+    }
+    if !path.is_dir() {
+        return Response::method_not_allowed(allowed_methods(args)).send_to(stream, exchange);
+    }
+    let allowed = |path: &Path| is_visible(args, path);
+    let location = encode_path(&request.path);
+    upload::post(
+        stream,
+        exchange,
+        &path,
+        &location,
+        body,
+        args.on_conflict,
+        &allowed,
+    )
+}
+
+/// Resolves the existing path of a request that changes it, which can't be
+/// the root.
+fn resolve_existing(args: &Args, request_path: &str) -> Result<PathBuf, Denied> {
+    let path = resolve(&args.path, request_path, args.symlinks)?;
+    if !is_visible(args, &path) {
+        return Err(Denied::NotFound);
+    }
+    if path == args.path {
+        return Err(Denied::Forbidden);
+    }
+    Ok(path)
+}
+
+fn remove<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    request: &Request,
+    args: &Args,
+) -> io::Result<usize> {
+    match resolve_existing(args, &request.path) {
+        Ok(path) => manage::delete(stream, exchange, &path, args.recursive_delete, |path| {
+            is_visible(args, path)
+        }),
+        Err(denied) => deny(stream, exchange, denied),
+    }
+}
+
+fn make_dir<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    request: &Request,
+    args: &Args,
+) -> io::Result<usize> {
+    let path = match resolve_new(&args.path, &request.path, args.symlinks) {
+        Ok(path) => path,
+        Err(denied) => return deny(stream, exchange, denied),
+    };
+    if !is_visible(args, &path) {
+        return deny(stream, exchange, Denied::Forbidden);
+    }
+    manage::make_dir(stream, exchange, &path, allowed_methods(args))
+}
+
+/// Moves or copies the requested path to the one in the `Destination`
+/// header.
+fn transfer<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    request: &Request,
+    args: &Args,
+    copy: bool,
+) -> io::Result<usize> {
+    let from = match resolve_existing(args, &request.path) {
+        Ok(path) => path,
+        Err(denied) => return deny(stream, exchange, denied),
+    };
+    let destination = exchange.headers.get("Destination").unwrap_or("");
+    let Some(destination) = destination_path(destination).and_then(|p| decode_percent(p).ok())
+    else {
+        return Response::error(400, "Missing or invalid Destination").send_to(stream, exchange);
+    };
+    let to = match resolve_new(&args.path, &destination, args.symlinks) {
+        Ok(path) => path,
+        Err(denied) => return deny(stream, exchange, denied),
+    };
+    if !is_visible(args, &to) {
+        return deny(stream, exchange, Denied::Forbidden);
+    }
+    if copy {
+        manage::copy_to(
+            stream,
+            exchange,
+            &from,
+            &to,
+            args.recursive_delete,
+            |path| is_visible(args, path),
+        )
+    } else {
+        manage::move_to(
+            stream,
+            exchange,
+            &from,
+            &to,
+            args.recursive_delete,
+            |path| is_visible(args, path),
+        )
+    }
+}
+
+/// Answers PROPFIND, or PROPPATCH, for the requested path.
+fn properties<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    request: &Request,
+    body: &mut R,
+    args: &Args,
+) -> io::Result<usize> {
+    let base = args.path.as_path();
+    let path = match resolve(base, &request.path, args.symlinks) {
+        Ok(path) => path,
+        Err(denied) => return deny(stream, exchange, denied),
+    };
+    if !is_visible(args, &path) {
+        return deny(stream, exchange, Denied::NotFound);
+    }
+    if request.method == "PROPPATCH" {
+        return webdav::proppatch(stream, exchange, base, &path, body);
+    }
+    // entries that can't be served aren't listed
+    let visible =
+        |entry: &Path| is_visible(args, entry) && confine(base, entry, args.symlinks).is_ok();
+    webdav::propfind(stream, exchange, base, &path, body, &visible)
+}
+
+/// Methods served, which depend on whether changing files and WebDAV are
+/// enabled.
+fn allowed_methods(args: &Args) -> &'static str {
+    match (args.write, args.webdav) {
+        (false, false) => "GET, HEAD, OPTIONS",
+        (true, false) => "GET, HEAD, OPTIONS, POST, PUT, DELETE, MKCOL, MOVE",
+        (false, true) => "GET, HEAD, OPTIONS, PROPFIND",
+        (true, true) => {
+            "GET, HEAD, OPTIONS, POST, PUT, DELETE, MKCOL, COPY, MOVE, PROPFIND, PROPPATCH"
+        }
+    }
+}
+
+/// Hidden paths are answered as if they didn't exist.
+fn is_visible(args: &Args, path: &Path) -> bool {
+    args.visibility.allows(&args.path, path)
+}
+
+fn deny<W: Write>(stream: &mut W, exchange: &Exchange, denied: Denied) -> io::Result<usize> {
+    match denied {
+        Denied::BadRequest(reason) => Response::error(400, reason).send_to(stream, exchange),
+        Denied::Forbidden => Response::error(403, "Access to the requested path is denied.")
+            .send_to(stream, exchange),
+        Denied::NotFound => {
+            Response::error(404, "Requested path does not exist.").send_to(stream, exchange)
+        }
+    }
+}
+
+/// Turns a connection away with an error, e.g. 503 when all workers are busy
+/// and the queue is full. This runs on the accepting thread, so it must not
+/// block: the request is not read, and whatever part of it already arrived is
+/// discarded so that closing the socket doesn't reset the connection before
+/// the client reads the response.
+///
+/// A TLS connection is closed without a response instead, as one could only
+/// be sent after a handshake, which the accepting thread can't wait for.
+fn reject(mut stream: TcpStream, response: &Response, tls: bool) -> io::Result<usize> {
+    if tls {
+        return Ok(0);
+    }
+    stream.set_nonblocking(true)?;
+    let mut buf = [0; 4096];
+    let _ = stream.read(&mut buf);
+
+    let exchange = Exchange {
+        headers: &Headers::new(),
+        keep_alive: None,
+        head: false,
+        compress: false,
+        chunked: false,
+        hsts: None,
+    };
+    let mut writer = BufWriter::new(&stream);
+    let written = response.send_to(&mut writer, &exchange)?;
+    writer.flush()?;
+    drop(writer);
+    stream.shutdown(Shutdown::Write)?;
+    Ok(written)
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let args = Args::parse(&args);
     let port = args.port;
+    let auth = Auth::load(&args.auth).unwrap_or_else(|e| {
+        eprintln!("{}", "Couldn't set up authentication".bright_red());
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let tls = Tls::load(&args.tls).unwrap_or_else(|e| {
+        eprintln!("{}", "Couldn't set up HTTPS".bright_red());
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let scheme = if tls.is_some() { "https" } else { "http" };
 
     println!("{} {}", "Rup version:".yellow(), cli::VERSION.green());
     println!(
         "{} {}:{}",
         "Starting server".yellow(),
-        "on http://localhost".green(),
+        format!("on {scheme}://localhost").green(),
         port
     );
 
-    let listener = TcpListener::bind(format!("0.0.0.0:{port}")).unwrap_or_else(|e| {
-        eprintln!("{}", "Couldn't bind the port".bright_red());
-        eprintln!("{e}");
-        process::exit(1);
-    });
+    let listener = bind(port);
+    let redirect_listener = args.tls.redirect_port.map(bind);
     println!(
         "{} {}",
         "Serving ".yellow(),
         args.path.canonicalize().unwrap().to_str().unwrap().green()
     );
+    if auth.is_some() {
+        println!(
+            "{} {}",
+            "Credentials required for".yellow(),
+            args.auth.realm.green()
+        );
+    }
+    if let Some(tls) = &tls {
+        if let Some(names) = tls.generated_for() {
+            println!(
+                "{} {}",
+                "Self-signed certificate for".yellow(),
+                names.join(", ").green()
+            );
+        }
+        println!(
+            "{} {}",
+            "Certificate".yellow(),
+            tls.cert_path().display().to_string().green()
+        );
+        println!(
+            "{} {}",
+            "SHA-256 fingerprint".yellow(),
+            tls.fingerprint().green()
+        );
+    }
+    if let Some(redirect_port) = args.tls.redirect_port {
+        println!(
+            "{} {}:{} {}",
+            "Redirecting".yellow(),
+            "http://localhost".green(),
+            redirect_port,
+            "to HTTPS".yellow()
+        );
+    }
+    if args.throttle.is_enabled() {
+        println!("{}", "Simulating a slow network".yellow());
+    }
     println!("Hit Ctrl+C to exit.\n");
-    let base_path = args.path;
+    let args = Arc::new(args);
+    let auth = auth.map(Arc::new);
+    let secure = tls.is_some();
+    let tls = tls.map(Arc::new);
+    let limiter = Arc::new(Limiter::new(&args.limits));
+    let pool = {
+        let args = Arc::clone(&args);
+        Pool::new(
+            args.workers.get(),
+            args.queue,
+            move |(stream, connection)| match handle_connection(
+                stream,
+                connection,
+                Arc::clone(&args),
+                auth.clone(),
+                tls.clone(),
+            ) {
+                Ok(_) => {}
+                Err(e) => eprintln!("{e}"),
+            },
+        )
+    };
+    if let Some(listener) = redirect_listener {
+        let args = Arc::clone(&args);
+        let limiter = Arc::clone(&limiter);
+        let pool = {
+            let args = Arc::clone(&args);
+            Pool::new(
+                args.workers.get(),
+                args.queue,
+                move |(stream, connection)| {
+                    if let Err(e) = redirect_connection(stream, connection, Arc::clone(&args)) {
+                        eprintln!("{e}");
+                    }
+                },
+            )
+        };
+        thread::spawn(move || listen(listener, &args, &limiter, &pool, false));
+    }
+    listen(listener, &args, &limiter, &pool, secure);
+}
+
+fn bind(port: u16) -> TcpListener {
+    TcpListener::bind(format!("0.0.0.0:{port}")).unwrap_or_else(|e| {
+        eprintln!("{}", "Couldn't bind the port".bright_red());
+        eprintln!("{e}");
+        process::exit(1);
+    })
+}
+
+/// Accepts connections on `listener` for `pool` to serve, turning away
+/// those the access rules or limits don't let in. Clients of a `tls`
+/// listener are turned away by closing the connection.
+fn listen(
+    listener: TcpListener,
+    args: &Args,
+    limiter: &Arc<Limiter>,
+    pool: &Pool<(TcpStream, Connection)>,
+    tls: bool,
+) {
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                let path = base_path.clone();
-                thread::spawn(move || match handle_connection(stream, path) {
-                    Ok(_) => {}
-                    Err(e) => eprintln!("{e}"),
-                });
+                let ip = match stream.peer_addr() {
+                    Ok(addr) => addr.ip(),
+                    // the client is gone already
+                    Err(_) => continue,
+                };
+                let result = match args.access.check(ip) {
+                    IpAction::Allow => match limiter.connect(ip) {
+                        Some(connection) => match pool.try_execute((stream, connection)) {
+                            Ok(()) => Ok(0),
+                            Err((stream, _)) => {
+                                reject(stream, &Response::error(503, "Server is busy"), tls)
+                            }
+                        },
+                        None => reject(stream, &Response::too_many_requests(1), tls),
+                    },
+                    IpAction::Deny => {
+                        let reason = format!("Connections from {ip} are denied");
+                        reject(stream, &Response::error(403, &reason), tls)
+                    }
+                    IpAction::Drop => {
+                        eprintln!("{} {ip}", "Dropped connection from".red());
+                        Ok(0)
+                    }
+                };
+                if let Err(e) = result {
+                    eprintln!("{e}");
+                }
             }
             Err(e) => {
                 eprintln!("failed: {e}");
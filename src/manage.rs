@@ -0,0 +1,482 @@
+use std::fs::{self, Metadata};
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+
+use crate::conditional::{Precondition, Validators, evaluate_write};
+use crate::headers::Headers;
+use crate::http::{Exchange, Response};
+use crate::upload::temp_path;
+
+/// Deletes the file, symlink or directory at `path`, answering 204. Only
+/// empty directories are deleted unless `recursive`, and never those
+/// holding entries that aren't `visible`; symlinks are removed rather than
+/// what they point to.
+pub(crate) fn delete<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    path: &Path,
+    recursive: bool,
+    visible: impl Fn(&Path) -> bool,
+) -> io::Result<usize> {
+    let Ok(md) = fs::symlink_metadata(path) else {
+        return Response::error(404, "Requested path does not exist.").send_to(stream, exchange);
+    };
+    if evaluate_write(exchange.headers, Some(&validators(&md))) == Precondition::Failed {
+        return Response::error(412, "The requested path has changed.").send_to(stream, exchange);
+    }
+    if let Err((code, reason)) = check_hidden(path, &visible) {
+        return Response::error(code, &reason).send_to(stream, exchange);
+    }
+    match remove(path, &md, recursive) {
+        Ok(()) => Response::deleted().send_to(stream, exchange),
+        Err(e) => removal_failed(stream, exchange, e),
+    }
+}
+
+/// Creates the directory at `path`, whose parent must exist, answering
+/// 201. The method is MKCOL, as in WebDAV, which also says how to fail:
+/// with 405 and the methods in `allow` if something exists at `path`.
+pub(crate) fn make_dir<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    path: &Path,
+    allow: &str,
+) -> io::Result<usize> {
+    if has_body(exchange.headers) {
+        return Response::error(415, "MKCOL doesn't take a body.").send_to(stream, exchange);
+    }
+    if fs::symlink_metadata(path).is_ok() {
+        return Response::method_not_allowed(allow).send_to(stream, exchange);
+    }
+    if !path.parent().unwrap().is_dir() {
+        return Response::error(409, "Parent directory does not exist.").send_to(stream, exchange);
+    }
+    match fs::create_dir(path) {
+        Ok(()) => Response::stored(path, true).send_to(stream, exchange),
+        Err(e) => {
+            let reason = format!("Fail to create directory: {e}");
+            Response::error(500, &reason).send_to(stream, exchange)
+        }
+    }
+}
+
+/// Moves the file or directory at `from` to `to`, answering 201 if nothing
+/// was there and 204 if it was replaced. `Overwrite: F` forbids replacing,
+/// and a directory in the way is only replaced if it is empty or
+/// `recursive`. A directory holding entries that aren't `visible` is neither
+/// moved, as they might be visible under the new name, nor replaced.
+pub(crate) fn move_to<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    from: &Path,
+    to: &Path,
+    recursive: bool,
+    visible: impl Fn(&Path) -> bool,
+) -> io::Result<usize> {
+    if let Err((code, reason)) = check_hidden(from, &visible) {
+        return Response::error(code, &reason).send_to(stream, exchange);
+    }
+    let replaced = match clear_destination(exchange.headers, from, to, recursive, &visible) {
+        Ok(replaced) => replaced,
+        Err((code, reason)) => return Response::error(code, &reason).send_to(stream, exchange),
+    };
+    match fs::rename(from, to) {
+        Ok(()) => Response::stored(to, !replaced).send_to(stream, exchange),
+        Err(e) => {
+            let reason = format!("Fail to move: {e}");
+            Response::error(500, &reason).send_to(stream, exchange)
+        }
+    }
+}
+
+/// Copies the file or directory at `from` to `to`, answering like
+/// [`move_to`]. Directories are copied with their content, unless the
+/// request has `Depth: 0`. Symlinks and entries that aren't `visible` are
+/// skipped, so that a copy never takes in files from outside the root or
+/// reveals hidden ones under a new name, and a directory holding hidden
+/// entries isn't replaced.
+pub(crate) fn copy_to<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    from: &Path,
+    to: &Path,
+    recursive: bool,
+    visible: impl Fn(&Path) -> bool,
+) -> io::Result<usize> {
+    let deep = match exchange.headers.get("Depth").map(str::trim) {
+        None => true,
+        Some(depth) if depth.eq_ignore_ascii_case("infinity") => true,
+        Some("0") => false,
+        Some(_) => return Response::error(400, "Invalid Depth").send_to(stream, exchange),
+    };
+    let replaced = match clear_destination(exchange.headers, from, to, recursive, &visible) {
+        Ok(replaced) => replaced,
+        Err((code, reason)) => return Response::error(code, &reason).send_to(stream, exchange),
+    };
+    let copied = if from.is_dir() {
+        copy_dir(from, to, deep, &visible)
+    } else {
+        copy_file(from, to)
+    };
+    match copied {
+        Ok(()) => Response::stored(to, !replaced).send_to(stream, exchange),
+        Err(e) => {
+            let reason = format!("Fail to copy: {e}");
+            Response::error(500, &reason).send_to(stream, exchange)
+        }
+    }
+}
+
+/// Checks that `from` can be moved or copied to `to`, and removes what is
+/// in the way if it is to be replaced, unless renaming replaces it anyway.
+/// Returns whether something is replaced, or the status and reason to
+/// answer with.
+fn clear_destination(
+    headers: &Headers,
+    from: &Path,
+    to: &Path,
+    recursive: bool,
+    visible: &impl Fn(&Path) -> bool,
+) -> Result<bool, (u16, String)> {
+    if to.starts_with(from) {
+        return Err((403, "A path can't be moved or copied into itself.".into()));
+    }
+    if !to.parent().unwrap().is_dir() {
+        return Err((409, "Destination directory does not exist.".into()));
+    }
+    let Ok(md) = fs::symlink_metadata(to) else {
+        return Ok(false);
+    };
+    if !overwrite(headers) {
+        return Err((412, "The destination exists.".into()));
+    }
+    // renaming replaces a file with a file, or an empty directory with a
+    // directory, but nothing else
+    if md.is_dir() || from.is_dir() {
+        check_hidden(to, visible)?;
+        remove(to, &md, recursive).map_err(|e| match e.kind() {
+            ErrorKind::DirectoryNotEmpty => (409, "Directory is not empty.".into()),
+            _ => (500, format!("Fail to delete: {e}")),
+        })?;
+    }
+    Ok(true)
+}
+
+/// Copies a file through a temporary file, so that the destination is
+/// replaced at once.
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    let temp = temp_path(to);
+    let copied = fs::copy(from, &temp).and_then(|_| fs::rename(&temp, to));
+    if copied.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    copied
+}
+
+/// Copies directory `from` to a new directory `to`, with its `visible`
+/// content if `deep`.
+fn copy_dir(
+    from: &Path,
+    to: &Path,
+    deep: bool,
+    visible: &impl Fn(&Path) -> bool,
+) -> io::Result<()> {
+    fs::create_dir(to)?;
+    if !deep {
+        return Ok(());
+    }
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !visible(&path) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&path, &target, true, visible)?;
+        } else if file_type.is_file() {
+            fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Refuses to take away a directory holding entries that aren't `visible`:
+/// the client can't see them, so it can't mean to move or delete them.
+fn check_hidden(path: &Path, visible: &impl Fn(&Path) -> bool) -> Result<(), (u16, String)> {
+    match has_hidden(path, visible) {
+        Ok(false) => Ok(()),
+        Ok(true) => Err((403, "The directory contains hidden files.".into())),
+        Err(e) => Err((500, format!("Fail to read directory: {e}"))),
+    }
+}
+
+/// Whether directory `path` holds anything that isn't `visible`, at any
+/// depth. Symlinks are checked but not followed.
+fn has_hidden(path: &Path, visible: &impl Fn(&Path) -> bool) -> io::Result<bool> {
+    if !fs::symlink_metadata(path)?.is_dir() {
+        return Ok(false);
+    }
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !visible(&path) || (entry.file_type()?.is_dir() && has_hidden(&path, visible)?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Path of a `Destination` header value, which is either an absolute URI or
+/// an absolute path, without the query. The host of a URI isn't checked, as
+/// the server doesn't know the names it is reached by.
+pub(crate) fn destination_path(value: &str) -> Option<&str> {
+    let value = value.trim();
+    let path = if value.starts_with('/') {
+        value
+    } else {
+        let (scheme, rest) = value.split_once("://")?;
+        if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+            return None;
+        }
+        match rest.find('/') {
+            Some(i) => &rest[i..],
+            None => "/",
+        }
+    };
+    path.split(['?', '#']).next()
+}
+
+fn validators(md: &Metadata) -> Validators {
+    if md.is_dir() {
+        Validators::listing(md)
+    } else {
+        Validators::from_metadata(md)
+    }
+}
+
+fn remove(path: &Path, md: &Metadata, recursive: bool) -> io::Result<()> {
+    if !md.is_dir() {
+        fs::remove_file(path)
+    } else if recursive {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_dir(path)
+    }
+}
+
+fn removal_failed<W: Write>(
+    stream: &mut W,
+    exchange: &Exchange,
+    e: io::Error,
+) -> io::Result<usize> {
+    if e.kind() == ErrorKind::DirectoryNotEmpty {
+        return Response::error(409, "Directory is not empty.").send_to(stream, exchange);
+    }
+    let reason = format!("Fail to delete: {e}");
+    Response::error(500, &reason).send_to(stream, exchange)
+}
+
+fn overwrite(headers: &Headers) -> bool {
+    headers
+        .get("Overwrite")
+        .is_none_or(|value| !value.trim().eq_ignore_ascii_case("F"))
+}
+
+fn has_body(headers: &Headers) -> bool {
+    headers.contains("Transfer-Encoding")
+        || headers
+            .get("Content-Length")
+            .is_some_and(|len| len.trim() != "0")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+    use std::process;
+
+    #[p_test(
+        ("/a/b.txt", Some("/a/b.txt")),
+        ("http://localhost:3000/a/b%20c.txt", Some("/a/b%20c.txt")),
+        ("HTTPS://example.com/a?x=1", Some("/a")),
+        ("http://example.com", Some("/")),
+        ("ftp://example.com/a", None),
+        ("a/b.txt", None),
+        ("", None),
+    )]
+    fn test_destination_path(value: &str, expected: Option<&str>) {
+        assert_eq!(destination_path(value), expected);
+    }
+
+    fn status(fields: &[(&str, &str)], op: impl FnOnce(&mut Vec<u8>, &Exchange)) -> String {
+        let mut headers = Headers::new();
+        for (name, value) in fields {
+            headers.append(name, value);
+        }
+        let exchange = Exchange {
+            headers: &headers,
+            keep_alive: None,
+            head: false,
+            compress: false,
+            chunked: true,
+            hsts: None,
+        };
+        let mut out = vec![];
+        op(&mut out, &exchange);
+        let out = String::from_utf8(out).unwrap();
+        out.lines().next().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_manage() {
+        let dir = std::env::temp_dir().join(format!("rup-manage-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b, sub) = (dir.join("a"), dir.join("b"), dir.join("sub"));
+
+        let mkdir = |fields: &[(&str, &str)], path: &Path| {
+            status(fields, |out, ex| {
+                make_dir(out, ex, path, "GET").unwrap();
+            })
+        };
+        assert_eq!(mkdir(&[], &sub), "HTTP/1.1 201 Created");
+        assert!(sub.is_dir());
+        assert_eq!(mkdir(&[], &sub), "HTTP/1.1 405 Method Not Allowed");
+        assert_eq!(mkdir(&[], &dir.join("x/y")), "HTTP/1.1 409 Conflict");
+        let fields = [("Content-Length", "3")];
+        let status_line = mkdir(&fields, &dir.join("z"));
+        assert_eq!(status_line, "HTTP/1.1 415 Unsupported Media Type");
+
+        let mv = |fields: &[(&str, &str)], from: &Path, to: &Path, recursive: bool| {
+            status(fields, |out, ex| {
+                move_to(out, ex, from, to, recursive, |_| true).unwrap();
+            })
+        };
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+        let fields = [("Overwrite", "F")];
+        assert_eq!(
+            mv(&fields, &a, &b, false),
+            "HTTP/1.1 412 Precondition Failed"
+        );
+        assert_eq!(mv(&[], &a, &b, false), "HTTP/1.1 204 No Content");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "a");
+        assert!(!a.exists());
+        assert_eq!(mv(&[], &b, &sub.join("b"), false), "HTTP/1.1 201 Created");
+        assert_eq!(
+            mv(&[], &sub, &sub.join("c"), false),
+            "HTTP/1.1 403 Forbidden"
+        );
+        fs::create_dir(&a).unwrap();
+        fs::write(a.join("x"), "x").unwrap();
+        assert_eq!(mv(&[], &sub, &a, false), "HTTP/1.1 409 Conflict");
+        assert_eq!(mv(&[], &sub, &a, true), "HTTP/1.1 204 No Content");
+        assert!(a.join("b").exists() && !a.join("x").exists());
+
+        let del = |fields: &[(&str, &str)], path: &Path, recursive: bool| {
+            status(fields, |out, ex| {
+                delete(out, ex, path, recursive, |_| true).unwrap();
+            })
+        };
+        let fields = [("If-Match", "\"other\"")];
+        let status_line = del(&fields, &a.join("b"), false);
+        assert_eq!(status_line, "HTTP/1.1 412 Precondition Failed");
+        assert_eq!(del(&[], &a, false), "HTTP/1.1 409 Conflict");
+        assert_eq!(del(&[], &a.join("b"), false), "HTTP/1.1 204 No Content");
+        assert_eq!(del(&[], &a, false), "HTTP/1.1 204 No Content");
+        assert!(!a.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy() {
+        let dir = std::env::temp_dir().join(format!("rup-copy-{}", process::id()));
+        let src = dir.join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("a"), "a").unwrap();
+        fs::write(src.join("nested/b"), "b").unwrap();
+
+        let cp = |fields: &[(&str, &str)], from: &Path, to: &Path| {
+            status(fields, |out, ex| {
+                copy_to(out, ex, from, to, true, |_| true).unwrap();
+            })
+        };
+        assert_eq!(cp(&[], &src, &dir.join("deep")), "HTTP/1.1 201 Created");
+        assert_eq!(fs::read_to_string(dir.join("deep/nested/b")).unwrap(), "b");
+        let fields = [("Depth", "0")];
+        assert_eq!(
+            cp(&fields, &src, &dir.join("shallow")),
+            "HTTP/1.1 201 Created"
+        );
+        assert_eq!(fs::read_dir(dir.join("shallow")).unwrap().count(), 0);
+        let fields = [("Depth", "1")];
+        assert_eq!(
+            cp(&fields, &src, &dir.join("x")),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(cp(&[], &src, &src.join("x")), "HTTP/1.1 403 Forbidden");
+
+        let (a, b) = (src.join("a"), dir.join("deep/nested/b"));
+        let fields = [("Overwrite", "F")];
+        assert_eq!(cp(&fields, &a, &b), "HTTP/1.1 412 Precondition Failed");
+        assert_eq!(cp(&[], &a, &b), "HTTP/1.1 204 No Content");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "a");
+        assert_eq!(cp(&[], &a, &dir.join("deep")), "HTTP/1.1 204 No Content");
+        assert!(dir.join("deep").is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hidden_entries() {
+        let dir = std::env::temp_dir().join(format!("rup-hidden-{}", process::id()));
+        let src = dir.join("src");
+        fs::create_dir_all(src.join("nested/.git")).unwrap();
+        fs::write(src.join("a"), "a").unwrap();
+        fs::write(src.join(".env"), "SECRET=1").unwrap();
+        fs::write(src.join("nested/.git/config"), "").unwrap();
+        let visible = |path: &Path| !path.iter().any(|s| s.to_string_lossy().starts_with('.'));
+
+        let copy = dir.join("copy");
+        let status_line = status(&[], |out, ex| {
+            copy_to(out, ex, &src, &copy, false, visible).unwrap();
+        });
+        assert_eq!(status_line, "HTTP/1.1 201 Created");
+        assert!(copy.join("a").exists() && copy.join("nested").is_dir());
+        assert!(!copy.join(".env").exists() && !copy.join("nested/.git").exists());
+
+        let moved = dir.join("moved");
+        let status_line = status(&[], |out, ex| {
+            move_to(out, ex, &src, &moved, false, visible).unwrap();
+        });
+        assert_eq!(status_line, "HTTP/1.1 403 Forbidden");
+        assert!(src.join(".env").exists() && !moved.exists());
+        let status_line = status(&[], |out, ex| {
+            move_to(out, ex, &copy, &moved, false, visible).unwrap();
+        });
+        assert_eq!(status_line, "HTTP/1.1 201 Created");
+
+        // nor are directories with hidden entries deleted or replaced
+        let status_line = status(&[], |out, ex| {
+            delete(out, ex, &src, true, visible).unwrap();
+        });
+        assert_eq!(status_line, "HTTP/1.1 403 Forbidden");
+        let status_line = status(&[], |out, ex| {
+            copy_to(out, ex, &moved, &src, true, visible).unwrap();
+        });
+        assert_eq!(status_line, "HTTP/1.1 403 Forbidden");
+        let status_line = status(&[], |out, ex| {
+            move_to(out, ex, &moved, &src, true, visible).unwrap();
+        });
+        assert_eq!(status_line, "HTTP/1.1 403 Forbidden");
+        assert!(src.join(".env").exists() && src.join("nested/.git/config").exists());
+        let status_line = status(&[], |out, ex| {
+            delete(out, ex, &moved, true, visible).unwrap();
+        });
+        assert_eq!(status_line, "HTTP/1.1 204 No Content");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1555,6 +1555,23 @@ pub(crate) fn mime(ext: &str) -> &'static str {
     }
 }
 
+/// Whether content of `mime_type` is worth compressing. Images, audio,
+/// video and archives are mostly compressed already.
+pub(crate) fn compressible(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || mime_type.ends_with("+xml")
+        || mime_type.ends_with("+json")
+        || matches!(
+            mime_type,
+            "application/javascript"
+                | "application/json"
+                | "application/xml"
+                | "application/wasm"
+                | "image/vnd.microsoft.icon"
+                | "image/bmp"
+        )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1580,4 +1597,18 @@ mod test {
     fn test_mime_types_basics(input: &str, expected: &str) {
         assert_eq!(mime(input), expected);
     }
+
+    #[p_test(
+        ("html", true),
+        ("js", true),
+        ("json", true),
+        ("svg", true),
+        ("png", false),
+        ("zip", false),
+        ("gz", false),
+        ("unknown", false),
+    )]
+    fn test_compressible(ext: &str, expected: bool) {
+        assert_eq!(compressible(mime(ext)), expected);
+    }
 }
@@ -0,0 +1,282 @@
+use std::io::{self, ErrorKind, Read};
+
+use crate::headers::Headers;
+
+/// Longest header line of a part accepted.
+const MAX_LINE: usize = 4 * 1024;
+/// Largest header section of a part accepted.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+const READ_SIZE: usize = 64 * 1024;
+
+/// Reads the parts of a `multipart/form-data` body (RFC 7578) as they
+/// arrive, without holding more than a buffer of it in memory. `next_part`
+/// moves to the next part and returns its header fields; reading then yields
+/// the content of that part.
+pub(crate) struct Multipart<R: Read> {
+    reader: R,
+    /// Line break and dashes followed by the boundary, which ends every part.
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    pos: usize,
+    /// The content of the current part has been read up to the delimiter.
+    at_delimiter: bool,
+    /// The closing delimiter has been reached.
+    done: bool,
+}
+
+impl<R: Read> Multipart<R> {
+    pub(crate) fn new(reader: R, boundary: &str) -> Self {
+        Multipart {
+            reader,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // the first delimiter has no line break before it unless there is
+            // a preamble, which is read as the content of a part to be skipped
+            buf: b"\r\n".to_vec(),
+            pos: 0,
+            at_delimiter: false,
+            done: false,
+        }
+    }
+
+    /// Skips what is left of the current part and reads the header fields of
+    /// the next one, or returns `None` after the last part.
+    pub(crate) fn next_part(&mut self) -> io::Result<Option<Headers>> {
+        if self.done {
+            return Ok(None);
+        }
+        io::copy(self, &mut io::sink())?;
+
+        if self.peek(2)? == b"--" {
+            self.done = true;
+            return Ok(None);
+        }
+        // transport padding may follow the delimiter
+        if !self.line()?.trim_matches([' ', '\t']).is_empty() {
+            return Err(invalid("Invalid multipart delimiter"));
+        }
+        let mut headers = Headers::new();
+        let mut size = 0;
+        loop {
+            let line = self.line()?;
+            if line.is_empty() {
+                break;
+            }
+            size += line.len();
+            if size > MAX_HEAD_SIZE {
+                return Err(invalid("Part header section is too large"));
+            }
+            headers.parse_line(&line).map_err(|e| invalid(&e))?;
+        }
+        self.at_delimiter = false;
+        Ok(Some(headers))
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Reads more of the body into the buffer, returning false at its end.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        let len = self.buf.len();
+        self.buf.resize(len + READ_SIZE, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buf[len..]) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                read => break read,
+            }
+        };
+        self.buf.truncate(len + *read.as_ref().unwrap_or(&0));
+        Ok(read? > 0)
+    }
+
+    /// Returns up to the next `n` bytes without consuming them.
+    fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        while self.data().len() < n {
+            if !self.fill()? {
+                break;
+            }
+        }
+        let data = self.data();
+        Ok(&data[..n.min(data.len())])
+    }
+
+    /// Reads a line, without the line break.
+    fn line(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(i) = self.data().iter().position(|&b| b == b'\n') {
+                let line = String::from_utf8_lossy(&self.data()[..i]);
+                let line = line.trim_end_matches('\r').to_string();
+                self.pos += i + 1;
+                return Ok(line);
+            }
+            if self.data().len() > MAX_LINE {
+                return Err(invalid("Part header line is too long"));
+            }
+            if !self.fill()? {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for Multipart<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        while !self.at_delimiter {
+            let data = self.data();
+            let len = match find(data, &self.delimiter) {
+                Some(0) => {
+                    self.pos += self.delimiter.len();
+                    self.at_delimiter = true;
+                    break;
+                }
+                Some(i) => i,
+                // the end of the buffer may be the start of the delimiter
+                None => data.len().saturating_sub(self.delimiter.len() - 1),
+            };
+            if len > 0 {
+                let len = len.min(out.len());
+                out[..len].copy_from_slice(&data[..len]);
+                self.pos += len;
+                return Ok(len);
+            }
+            if !self.fill()? {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(0)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, reason)
+}
+
+/// Value of parameter `name` in a header field value such as
+/// `form-data; name="file"; filename="a.txt"`, unquoted.
+pub(crate) fn parameter(value: &str, name: &str) -> Option<String> {
+    let mut rest = value.split_once(';')?.1;
+    loop {
+        let (key, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => unquote(quoted)?,
+            None => {
+                let end = after.find(';').unwrap_or(after.len());
+                (after[..end].trim().to_string(), &after[end..])
+            }
+        };
+        if key.trim().eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = after.split_once(';')?.1;
+    }
+}
+
+/// Reads a quoted string whose opening quote has been consumed, returning
+/// its content and what follows the closing quote.
+fn unquote(s: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &s[i + 1..])),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    /// Reads every part, returning its `Content-Disposition` and content.
+    /// The body is fed in tiny reads so that delimiters get split.
+    fn parts(body: &str) -> io::Result<Vec<(String, String)>> {
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = buf.len().min(self.0.len()).min(3);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let mut multipart = Multipart::new(Trickle(body.as_bytes()), "XyZ");
+        let mut parts = vec![];
+        while let Some(headers) = multipart.next_part()? {
+            let mut content = String::new();
+            multipart.read_to_string(&mut content)?;
+            let disposition = headers.get("Content-Disposition").unwrap_or("").to_string();
+            parts.push((disposition, content));
+        }
+        Ok(parts)
+    }
+
+    #[test]
+    fn test_parts() {
+        let body = "preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+            one\r\n--XyZ  \r\n\
+            Content-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            two\r\n--Xy\r\n\r\n--XyZ--\r\nepilogue";
+        let parts = parts(body).unwrap();
+        assert_eq!(
+            parts,
+            [
+                ("form-data; name=\"a\"".to_string(), "one".to_string()),
+                (
+                    "form-data; name=\"b\"; filename=\"b.txt\"".to_string(),
+                    "two\r\n--Xy\r\n".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[p_test(
+        ("--XyZ--", 0),
+        ("--XyZ\r\n\r\n\r\n--XyZ--", 1),
+        ("--XyZ\r\nX-A: 1\r\n\r\n\r\n--XyZ\r\n\r\n\r\n--XyZ--", 2),
+    )]
+    fn test_part_count(body: &str, expected: usize) {
+        assert_eq!(parts(body).unwrap().len(), expected);
+    }
+
+    #[p_test(
+        ("--XyZ\r\n\r\nunterminated", ErrorKind::UnexpectedEof),
+        ("--XyZ\r\nX-A: 1", ErrorKind::UnexpectedEof),
+        ("", ErrorKind::UnexpectedEof),
+        ("--XyZjunk\r\n\r\n\r\n--XyZ--", ErrorKind::InvalidData),
+        ("--XyZ\r\nnot a header\r\n\r\n\r\n--XyZ--", ErrorKind::InvalidData),
+    )]
+    fn test_invalid(body: &str, kind: ErrorKind) {
+        assert_eq!(parts(body).unwrap_err().kind(), kind);
+    }
+
+    #[p_test(
+        ("form-data; name=\"file\"; filename=\"a.txt\"", "filename", Some("a.txt")),
+        ("form-data; name=\"file\"; filename=\"a.txt\"", "name", Some("file")),
+        ("form-data; name=file; FILENAME = \"a\\\"b;c.txt\"", "filename", Some("a\"b;c.txt")),
+        ("multipart/form-data; boundary=----abc", "boundary", Some("----abc")),
+        ("multipart/form-data; charset=utf-8; boundary=\"a b\"", "boundary", Some("a b")),
+        ("form-data; name=\"file\"", "filename", None),
+        ("form-data", "name", None),
+        ("form-data; name=\"unterminated", "name", None),
+    )]
+    fn test_parameter(value: &str, name: &str, expected: Option<&str>) {
+        assert_eq!(parameter(value, name).as_deref(), expected);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Fixed number of worker threads taking jobs from a bounded queue.
+/// Submitting never blocks: when all workers are busy and the queue is full,
+/// the job is handed back so that the caller can turn it away. A job that
+/// panics is given up, but its worker goes on with the next one.
+pub(crate) struct Pool<T> {
+    sender: Option<SyncSender<T>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> Pool<T> {
+    /// Starts `size` workers running `handler` for every job, with room for
+    /// `queue` jobs waiting for a worker.
+    pub(crate) fn new<F>(size: usize, queue: usize, handler: F) -> Self
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                let handler = Arc::clone(&handler);
+                thread::Builder::new()
+                    .name(format!("worker-{id}"))
+                    .spawn(move || work(&receiver, &*handler))
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+        Pool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Queues `job` for the next free worker, or gives it back if the queue
+    /// is full.
+    pub(crate) fn try_execute(&self, job: T) -> Result<(), T> {
+        match self.sender.as_ref().unwrap().try_send(job) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(job) | TrySendError::Disconnected(job)) => Err(job),
+        }
+    }
+}
+
+fn work<T, F: Fn(T)>(receiver: &Mutex<Receiver<T>>, handler: &F) {
+    loop {
+        // the lock is released as soon as a job is received
+        let job = receiver.lock().unwrap().recv();
+        match job {
+            // the panic hook has reported it already; state shared between
+            // jobs is only updated under short locks that don't panic
+            Ok(job) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(job)));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+impl<T> Drop for Pool<T> {
+    /// Lets the workers finish queued jobs, then waits for them to exit.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_runs_all_jobs() {
+        let (done, results) = channel();
+        let done = Mutex::new(done);
+        let pool = Pool::new(4, 16, move |n: u32| {
+            done.lock().unwrap().send(n * 2).unwrap()
+        });
+        for n in 0..16 {
+            assert!(pool.try_execute(n).is_ok());
+        }
+        drop(pool);
+        let mut results: Vec<u32> = results.iter().collect();
+        results.sort();
+        assert_eq!(results, (0..16).map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_rejects_when_saturated() {
+        let (release, blocked) = channel::<()>();
+        let (started, running) = channel();
+        let blocked = Mutex::new(blocked);
+        let started = Mutex::new(started);
+        let pool = Pool::new(1, 1, move |_: u32| {
+            started.lock().unwrap().send(()).unwrap();
+            blocked.lock().unwrap().recv().unwrap();
+        });
+
+        assert_eq!(pool.try_execute(1), Ok(()));
+        running.recv().unwrap();
+        assert_eq!(pool.try_execute(2), Ok(()));
+        assert_eq!(pool.try_execute(3), Err(3));
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_survives_panics() {
+        let (done, results) = channel();
+        let done = Mutex::new(done);
+        let pool = Pool::new(1, 4, move |n: u32| {
+            assert!(n.is_multiple_of(2), "odd job");
+            done.lock().unwrap().send(n).unwrap();
+        });
+        for n in 0..4 {
+            assert!(pool.try_execute(n).is_ok());
+        }
+        drop(pool);
+        assert_eq!(results.iter().collect::<Vec<_>>(), [0, 2]);
+    }
+}
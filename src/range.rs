@@ -0,0 +1,160 @@
+use std::ops::Range;
+
+/// Upper bound on the number of ranges honoured in one request. Requests
+/// asking for more are answered with the whole representation.
+const MAX_RANGES: usize = 32;
+
+/// Outcome of evaluating a `Range` header against a representation.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Ranges {
+    /// The header is absent, malformed, uses a unit other than `bytes` or
+    /// asks for too many ranges; it must be ignored and a 200 sent.
+    Ignore,
+    /// None of the ranges overlaps the representation; 416 must be sent.
+    Unsatisfiable,
+    /// Byte ranges (end exclusive) to be sent with 206.
+    Satisfiable(Vec<Range<u64>>),
+}
+
+/// Parses the value of a `Range` header for a representation of `len` bytes.
+/// Overlapping ranges are coalesced, otherwise the requested order is kept.
+pub(crate) fn parse_range(value: &str, len: u64) -> Ranges {
+    let Some((unit, specs)) = value.split_once('=') else {
+        return Ranges::Ignore;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Ranges::Ignore;
+    }
+
+    let mut ranges = vec![];
+    let mut count = 0;
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        count += 1;
+        if count > MAX_RANGES {
+            return Ranges::Ignore;
+        }
+        match parse_spec(spec, len) {
+            Ok(Some(range)) => ranges.push(range),
+            Ok(None) => {}
+            Err(()) => return Ranges::Ignore,
+        }
+    }
+
+    if count == 0 {
+        Ranges::Ignore
+    } else if ranges.is_empty() {
+        Ranges::Unsatisfiable
+    } else {
+        Ranges::Satisfiable(coalesce(ranges))
+    }
+}
+
+/// Parses one `first-last`, `first-` or `-suffix` spec. `Ok(None)` is
+/// returned for a well-formed spec that doesn't overlap the representation.
+fn parse_spec(spec: &str, len: u64) -> Result<Option<Range<u64>>, ()> {
+    let (first, last) = spec.split_once('-').ok_or(())?;
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        let suffix = parse_u64(last)?;
+        if suffix == 0 || len == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(len.saturating_sub(suffix)..len));
+    }
+
+    let first = parse_u64(first)?;
+    let last = if last.is_empty() {
+        None
+    } else {
+        Some(parse_u64(last)?)
+    };
+    match last {
+        Some(last) if last < first => Err(()),
+        _ if first >= len => Ok(None),
+        Some(last) => Ok(Some(first..last.min(len - 1) + 1)),
+        None => Ok(Some(first..len)),
+    }
+}
+
+fn parse_u64(s: &str) -> Result<u64, ()> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(());
+    }
+    s.parse().map_err(|_| ())
+}
+
+fn coalesce(ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    let mut sorted = ranges.clone();
+    sorted.sort_by_key(|r| r.start);
+    if sorted.windows(2).all(|w| w[0].end <= w[1].start) {
+        return ranges;
+    }
+
+    let mut merged: Vec<Range<u64>> = vec![];
+    for range in sorted {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        ("bytes=0-499", vec![0..500]),
+        ("bytes=500-999", vec![500..1000]),
+        ("bytes=500-", vec![500..1000]),
+        ("bytes=-500", vec![500..1000]),
+        ("bytes=-5000", vec![0..1000]),
+        ("bytes=900-5000", vec![900..1000]),
+        ("bytes=0-0,-1", vec![0..1, 999..1000]),
+        ("bytes=500-599, 100-199", vec![500..600, 100..200]),
+        ("bytes=500-700,601-999", vec![500..1000]),
+        ("bytes=0-99,2000-,50-199", vec![0..200]),
+        ("Bytes = 10-19", vec![10..20]),
+    )]
+    fn test_satisfiable(value: &str, expected: Vec<Range<u64>>) {
+        assert_eq!(parse_range(value, 1000), Ranges::Satisfiable(expected));
+    }
+
+    #[p_test(
+        ("bytes=1000-"),
+        ("bytes=1000-2000"),
+        ("bytes=-0"),
+        ("bytes=1000-,2000-2999"),
+    )]
+    fn test_unsatisfiable(value: &str) {
+        assert_eq!(parse_range(value, 1000), Ranges::Unsatisfiable);
+    }
+
+    #[p_test(
+        ("items=0-5"),
+        ("bytes"),
+        ("bytes="),
+        ("bytes=5-1"),
+        ("bytes=a-b"),
+        ("bytes=0-1,x"),
+        ("bytes=+1-2"),
+        ("bytes=--1"),
+    )]
+    fn test_ignored(value: &str) {
+        assert_eq!(parse_range(value, 1000), Ranges::Ignore);
+    }
+
+    #[test]
+    fn test_too_many_ranges() {
+        let value = format!("bytes={}", vec!["0-1"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse_range(&value, 1000), Ranges::Ignore);
+    }
+
+    #[test]
+    fn test_empty_representation() {
+        assert_eq!(parse_range("bytes=0-", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-1", 0), Ranges::Unsatisfiable);
+    }
+}
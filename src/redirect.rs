@@ -0,0 +1,95 @@
+use std::net::Ipv6Addr;
+
+use crate::decode::encode_path;
+
+/// Status sending a client from plain HTTP to HTTPS: 301 for GET and HEAD,
+/// and otherwise 308, which tells clients to repeat the method and body
+/// rather than switch to GET.
+pub(crate) fn redirect_code(method: &str) -> u16 {
+    match method {
+        "GET" | "HEAD" => 301,
+        _ => 308,
+    }
+}
+
+/// The same resource on the HTTPS `port` of `host`, as named by the `Host`
+/// header of the request, or `None` if the host isn't a valid name or
+/// address.
+pub(crate) fn https_location(
+    host: &str,
+    port: u16,
+    path: &str,
+    query: Option<&str>,
+) -> Option<String> {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => {
+            let (addr, _) = rest.split_once(']')?;
+            addr.parse::<Ipv6Addr>().ok()?;
+            &host[..addr.len() + 2]
+        }
+        None => host.split(':').next()?,
+    };
+    let valid = match name.as_bytes().first() {
+        Some(b'[') => true,
+        Some(_) => name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_'),
+        None => false,
+    };
+    if !valid {
+        return None;
+    }
+    let port = match port {
+        443 => String::new(),
+        port => format!(":{port}"),
+    };
+    let path = match path.starts_with('/') {
+        true => encode_path(path),
+        false => "/".to_string(),
+    };
+    let query = query.map(|query| format!("?{query}")).unwrap_or_default();
+    Some(format!("https://{name}{port}{path}{query}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        ("localhost:8080", 8443, "/", None, "https://localhost:8443/"),
+        ("localhost", 443, "/a b", Some("x=1"), "https://localhost/a%20b?x=1"),
+        ("192.168.1.5:80", 3000, "/app/", None, "https://192.168.1.5:3000/app/"),
+        ("[::1]:8080", 3000, "/", Some(""), "https://[::1]:3000/?"),
+        ("[fe80::1]", 443, "/", None, "https://[fe80::1]/"),
+        ("My-Host.local", 3000, "*", None, "https://My-Host.local:3000/"),
+    )]
+    fn test_https_location(host: &str, port: u16, path: &str, query: Option<&str>, expected: &str) {
+        assert_eq!(
+            https_location(host, port, path, query).as_deref(),
+            Some(expected)
+        );
+    }
+
+    #[p_test(
+        (""),
+        (":8080"),
+        ("[::1"),
+        ("[localhost]:80"),
+        ("evil.com/path"),
+        ("user@host"),
+    )]
+    fn test_invalid_host(host: &str) {
+        assert_eq!(https_location(host, 3000, "/", None), None);
+    }
+
+    #[p_test(
+        ("GET", 301),
+        ("HEAD", 301),
+        ("POST", 308),
+        ("PUT", 308),
+    )]
+    fn test_redirect_code(method: &str, expected: u16) {
+        assert_eq!(redirect_code(method), expected);
+    }
+}
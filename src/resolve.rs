@@ -0,0 +1,260 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// Reasons a request path can't be mapped to a file under the root.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Denied {
+    /// The path contains characters that are never valid in a request path.
+    BadRequest(&'static str),
+    /// The path points outside of the root.
+    Forbidden,
+    /// Nothing exists at the path.
+    NotFound,
+}
+
+/// Which symlinks below the root may be followed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SymlinkPolicy {
+    /// Follow all symlinks, wherever they point to.
+    Follow,
+    /// Follow symlinks only if their target is located under the root.
+    Inside,
+    /// Never follow symlinks.
+    Never,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "follow" => Ok(SymlinkPolicy::Follow),
+            "inside" => Ok(SymlinkPolicy::Inside),
+            "never" => Ok(SymlinkPolicy::Never),
+            _ => Err("expected one of follow, inside, never".into()),
+        }
+    }
+}
+
+/// Maps a decoded request path onto the file system under `root`.
+/// Dot segments are resolved first, so that the path can't climb above the
+/// root, and the result is then checked against the symlink policy.
+pub(crate) fn resolve(
+    root: &Path,
+    request_path: &str,
+    policy: SymlinkPolicy,
+) -> Result<PathBuf, Denied> {
+    let segments = normalize(request_path)?;
+    let path = segments.iter().fold(root.to_path_buf(), |p, s| p.join(s));
+    confine(root, &path, policy)?;
+    Ok(path)
+}
+
+/// Maps a decoded request path onto a file under `root` that is about to be
+/// written and may not exist yet, nor its parent directories. The closest
+/// existing ancestor, or the file itself if it exists, must be reachable
+/// under the symlink policy. The root itself can't be written.
+pub(crate) fn resolve_new(
+    root: &Path,
+    request_path: &str,
+    policy: SymlinkPolicy,
+) -> Result<PathBuf, Denied> {
+    let segments = normalize(request_path)?;
+    if segments.is_empty() {
+        return Err(Denied::Forbidden);
+    }
+    let path = segments.iter().fold(root.to_path_buf(), |p, s| p.join(s));
+    let existing = path.ancestors().find(|p| p.exists()).unwrap_or(root);
+    confine(root, existing, policy)?;
+    Ok(path)
+}
+
+/// The file `request_path` leads to once symlinks are followed, relative to
+/// the real `root`, or `None` if it lies outside of it. Of a path that
+/// doesn't exist yet, only the existing ancestors are resolved.
+pub(crate) fn real_relative(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let segments = normalize(request_path).ok()?;
+    let path = segments.iter().fold(root.to_path_buf(), |p, s| p.join(s));
+    real_path(root, &path)
+}
+
+/// Like [`real_relative`], for a `path` already mapped under `root`.
+pub(crate) fn real_path(root: &Path, path: &Path) -> Option<PathBuf> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let real_root = root.canonicalize().ok()?;
+    let real = existing.canonicalize().ok()?;
+    let rest = path.strip_prefix(existing).ok()?;
+    Some(real.strip_prefix(&real_root).ok()?.join(rest))
+}
+
+/// Checks that an existing `path` under `root` can be reached without
+/// following symlinks the policy doesn't allow.
+pub(crate) fn confine(root: &Path, path: &Path, policy: SymlinkPolicy) -> Result<(), Denied> {
+    let real_root = root.canonicalize().map_err(|_| Denied::NotFound)?;
+    let real = path.canonicalize().map_err(|_| Denied::NotFound)?;
+    match policy {
+        SymlinkPolicy::Follow => Ok(()),
+        SymlinkPolicy::Inside if real.starts_with(&real_root) => Ok(()),
+        SymlinkPolicy::Never if !has_symlink(root, path) => Ok(()),
+        _ => Err(Denied::Forbidden),
+    }
+}
+
+/// Checks whether any component of `path` below `root` is a symlink.
+fn has_symlink(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return true;
+    };
+    let mut current = root.to_path_buf();
+    relative.components().any(|component| {
+        current.push(component);
+        fs::symlink_metadata(&current).map_or(true, |md| md.file_type().is_symlink())
+    })
+}
+
+/// Whether a directory entry is a symlink. Listings mark those, whether or not
+/// they can be followed.
+pub(crate) fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|md| md.file_type().is_symlink())
+}
+
+/// Splits a decoded request path into segments, resolving `.` and `..`.
+/// A `..` that would leave the root is an attempt to escape it.
+pub(crate) fn normalize(request_path: &str) -> Result<Vec<&str>, Denied> {
+    if request_path.contains('\0') {
+        return Err(Denied::BadRequest("NUL in request path"));
+    }
+    if request_path.contains('\\') {
+        return Err(Denied::BadRequest("Backslash in request path"));
+    }
+
+    let mut segments = vec![];
+    for segment in request_path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(Denied::Forbidden);
+                }
+            }
+            _ => {
+                // a segment must not turn into a prefix or root, e.g. `C:`
+                let mut components = Path::new(segment).components();
+                match (components.next(), components.next()) {
+                    (Some(Component::Normal(_)), None) => segments.push(segment),
+                    _ => return Err(Denied::BadRequest("Invalid request path")),
+                }
+            }
+        }
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    #[p_test(
+        ("/", vec![]),
+        ("/a/b.txt", vec!["a", "b.txt"]),
+        ("/a//b/", vec!["a", "b"]),
+        ("/./a/./b", vec!["a", "b"]),
+        ("/a/../b", vec!["b"]),
+        ("/a/b/../../c", vec!["c"]),
+        ("/a/..", vec![]),
+        ("/..a/b..", vec!["..a", "b.."]),
+    )]
+    fn test_normalize(input: &str, expected: Vec<&str>) {
+        assert_eq!(normalize(input), Ok(expected));
+    }
+
+    #[p_test(
+        ("/.."),
+        ("/../etc/passwd"),
+        ("/a/../../etc/passwd"),
+        ("/a/./../.."),
+    )]
+    fn test_escape(input: &str) {
+        assert_eq!(normalize(input), Err(Denied::Forbidden));
+    }
+
+    #[p_test(
+        ("/a\0.txt"),
+        ("/..\\..\\etc\\passwd"),
+    )]
+    fn test_invalid(input: &str) {
+        assert!(matches!(normalize(input), Err(Denied::BadRequest(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve() {
+        use SymlinkPolicy::*;
+
+        let dir = std::env::temp_dir().join(format!("rup-resolve-{}", std::process::id()));
+        let root = dir.join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/file.txt"), "file").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("outside.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join("sub"), root.join("inside")).unwrap();
+
+        for policy in [Follow, Inside, Never] {
+            let file = root.join("sub/file.txt");
+            assert_eq!(resolve(&root, "/sub/file.txt", policy), Ok(file.clone()));
+            assert_eq!(resolve(&root, "/sub/../sub/file.txt", policy), Ok(file));
+            assert_eq!(resolve(&root, "/", policy), Ok(root.clone()));
+            assert_eq!(
+                resolve(&root, "/missing.txt", policy),
+                Err(Denied::NotFound)
+            );
+            assert_eq!(
+                resolve(&root, "/../secret.txt", policy),
+                Err(Denied::Forbidden)
+            );
+        }
+
+        let outside = resolve(&root, "/outside.txt", Follow);
+        assert_eq!(outside, Ok(root.join("outside.txt")));
+        let outside = resolve(&root, "/outside.txt", Inside);
+        assert_eq!(outside, Err(Denied::Forbidden));
+        let outside = resolve(&root, "/outside.txt", Never);
+        assert_eq!(outside, Err(Denied::Forbidden));
+
+        let inside = resolve(&root, "/inside/file.txt", Inside);
+        assert_eq!(inside, Ok(root.join("inside/file.txt")));
+        let inside = resolve(&root, "/inside/file.txt", Never);
+        assert_eq!(inside, Err(Denied::Forbidden));
+
+        for policy in [Follow, Inside, Never] {
+            let new = root.join("sub/new/file.txt");
+            assert_eq!(resolve_new(&root, "/sub/new/file.txt", policy), Ok(new));
+            let existing = root.join("sub/file.txt");
+            assert_eq!(resolve_new(&root, "/sub/file.txt", policy), Ok(existing));
+            assert_eq!(resolve_new(&root, "/", policy), Err(Denied::Forbidden));
+            assert_eq!(
+                resolve_new(&root, "/../new.txt", policy),
+                Err(Denied::Forbidden)
+            );
+        }
+        let new = resolve_new(&root, "/inside/new.txt", Inside);
+        assert_eq!(new, Ok(root.join("inside/new.txt")));
+        let new = resolve_new(&root, "/inside/new.txt", Never);
+        assert_eq!(new, Err(Denied::Forbidden));
+        let outside = resolve_new(&root, "/outside.txt", Inside);
+        assert_eq!(outside, Err(Denied::Forbidden));
+
+        assert!(is_symlink(&root.join("inside")));
+        assert!(!is_symlink(&root.join("sub")));
+
+        let real = real_relative(&root, "/inside/file.txt");
+        assert_eq!(real, Some(PathBuf::from("sub/file.txt")));
+        let real = real_relative(&root, "/inside/new/file.txt");
+        assert_eq!(real, Some(PathBuf::from("sub/new/file.txt")));
+        assert_eq!(real_relative(&root, "/"), Some(PathBuf::new()));
+        assert_eq!(real_relative(&root, "/outside.txt"), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
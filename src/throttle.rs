@@ -0,0 +1,250 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    io::{self, Write},
+    num::NonZeroU64,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{resolve::normalize, visibility::rule_matches};
+
+/// Largest piece of a throttled response written at once.
+const CHUNK: usize = 4 * 1024;
+
+/// Network conditions to simulate: the bandwidth of the link, in bytes per
+/// second, and the latency before every response, which varies by up to the
+/// jitter either way.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Condition {
+    pub bandwidth: Option<NonZeroU64>,
+    pub latency: Duration,
+    pub jitter: Duration,
+}
+
+/// Conditions of typical mobile networks, by name.
+const PRESETS: [(&str, u64, u64, u64); 4] = [
+    // name, bytes per second, latency and jitter in milliseconds
+    ("slow-3g", 50_000, 2000, 200),
+    ("3g", 200_000, 560, 100),
+    ("slow-4g", 500_000, 150, 50),
+    ("4g", 1_125_000, 60, 20),
+];
+
+impl FromStr for Condition {
+    type Err = String;
+
+    /// Parses a preset, `none`, or comma-separated settings which may follow
+    /// a preset, e.g. `3g,latency=100` or `bandwidth=20000,jitter=50`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut condition = Condition::default();
+        for (i, item) in s.split(',').map(str::trim).enumerate() {
+            let Some((key, value)) = item.split_once('=') else {
+                let preset = PRESETS.iter().find(|preset| preset.0 == item);
+                condition = match preset {
+                    Some(&(_, bandwidth, latency, jitter)) if i == 0 => Condition {
+                        bandwidth: NonZeroU64::new(bandwidth),
+                        latency: Duration::from_millis(latency),
+                        jitter: Duration::from_millis(jitter),
+                    },
+                    None if i == 0 && item == "none" => Condition::default(),
+                    _ => {
+                        let names: Vec<&str> = PRESETS.iter().map(|preset| preset.0).collect();
+                        return Err(format!(
+                            "expected none, {} or settings such as latency=100",
+                            names.join(", ")
+                        ));
+                    }
+                };
+                continue;
+            };
+            let value: u64 = value
+                .parse()
+                .map_err(|_| format!("expected a number for {key}"))?;
+            match key {
+                "bandwidth" => condition.bandwidth = NonZeroU64::new(value),
+                "latency" => condition.latency = Duration::from_millis(value),
+                "jitter" => condition.jitter = Duration::from_millis(value),
+                _ => {
+                    return Err(format!(
+                        "unknown setting {key}, expected bandwidth, latency or jitter"
+                    ));
+                }
+            }
+        }
+        Ok(condition)
+    }
+}
+
+impl Condition {
+    /// Time to wait before responding: the latency, give or take up to the
+    /// jitter.
+    fn delay(&self) -> Duration {
+        let jitter = self.jitter.as_millis() as u64;
+        if jitter == 0 {
+            return self.latency;
+        }
+        let offset = RandomState::new().hash_one(Instant::now()) % (2 * jitter + 1);
+        (self.latency + Duration::from_millis(offset)).saturating_sub(self.jitter)
+    }
+
+    /// Waits as long as a response would take to start arriving.
+    pub(crate) fn wait(&self) {
+        let delay = self.delay();
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Simulated network conditions, for testing how pages behave when they
+/// load slowly. Paths are matched against the rules in order, like
+/// visibility rules, and paths no rule matches get the default conditions.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Throttle {
+    pub default: Option<Condition>,
+    pub rules: Vec<(String, Condition)>,
+}
+
+impl Throttle {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.default.is_some() || !self.rules.is_empty()
+    }
+
+    /// Conditions to simulate for a request for `request_path`.
+    pub(crate) fn condition(&self, request_path: &str) -> Option<Condition> {
+        let segments = normalize(request_path).unwrap_or_default();
+        self.rules
+            .iter()
+            .find(|(rule, _)| rule_matches(rule, &segments))
+            .map(|(_, condition)| *condition)
+            .or(self.default)
+    }
+}
+
+/// Writes to `inner` at a steady bandwidth, if any, as a slow link would.
+pub(crate) struct Throttled<W: Write> {
+    inner: W,
+    bandwidth: Option<NonZeroU64>,
+    start: Instant,
+    written: u64,
+}
+
+impl<W: Write> Throttled<W> {
+    pub(crate) fn new(inner: W, bandwidth: Option<NonZeroU64>) -> Self {
+        Throttled {
+            inner,
+            bandwidth,
+            start: Instant::now(),
+            written: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for Throttled<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(bandwidth) = self.bandwidth else {
+            return self.inner.write(buf);
+        };
+        let buf = &buf[..buf.len().min(CHUNK)];
+        self.written += buf.len() as u64;
+        // the chunk is sent once the link would have carried it
+        let due =
+            self.start + Duration::from_secs_f64(self.written as f64 / bandwidth.get() as f64);
+        let wait = due.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        self.inner.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    fn condition(bandwidth: u64, latency: u64, jitter: u64) -> Condition {
+        Condition {
+            bandwidth: NonZeroU64::new(bandwidth),
+            latency: Duration::from_millis(latency),
+            jitter: Duration::from_millis(jitter),
+        }
+    }
+
+    #[p_test(
+        ("3g", condition(200_000, 560, 100)),
+        ("slow-3g", condition(50_000, 2000, 200)),
+        ("3g, latency=100", condition(200_000, 100, 100)),
+        ("4g,bandwidth=0", condition(0, 60, 20)),
+        ("bandwidth=20000,jitter=50", condition(20_000, 0, 50)),
+        ("latency=300", condition(0, 300, 0)),
+        ("none", condition(0, 0, 0)),
+    )]
+    fn test_parse(s: &str, expected: Condition) {
+        assert_eq!(s.parse::<Condition>(), Ok(expected));
+    }
+
+    #[p_test(
+        ("5g"),
+        ("latency=100,3g"),
+        ("latency=fast"),
+        ("speed=100"),
+        (""),
+    )]
+    fn test_parse_invalid(s: &str) {
+        assert!(s.parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_delay() {
+        let condition = condition(0, 100, 20);
+        for _ in 0..100 {
+            let delay = condition.delay();
+            assert!(delay >= Duration::from_millis(80) && delay <= Duration::from_millis(120));
+        }
+        let condition = self::condition(0, 10, 50);
+        for _ in 0..100 {
+            assert!(condition.delay() <= Duration::from_millis(60));
+        }
+    }
+
+    #[p_test(
+        ("/index.html", 2000),
+        ("/app.js", 560),
+        ("/js/vendor/app.js", 560),
+        ("/api/items", 0),
+        ("/api/../app.js", 560),
+    )]
+    fn test_condition(path: &str, latency: u64) {
+        let throttle = Throttle {
+            default: Some("slow-3g".parse().unwrap()),
+            rules: vec![
+                ("*.js".to_string(), "3g".parse().unwrap()),
+                ("/api/".to_string(), "none".parse().unwrap()),
+            ],
+        };
+        let condition = throttle.condition(path).unwrap();
+        assert_eq!(condition.latency, Duration::from_millis(latency));
+        assert!(Throttle::default().condition(path).is_none());
+    }
+
+    #[test]
+    fn test_throttled() {
+        let mut out = vec![];
+        let start = Instant::now();
+        Throttled::new(&mut out, NonZeroU64::new(100_000))
+            .write_all(&[0; 20_000])
+            .unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(out.len(), 20_000);
+        assert!(elapsed >= Duration::from_millis(190), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+}
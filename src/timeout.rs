@@ -0,0 +1,126 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+/// `TcpStream` whose reads fail with `TimedOut` once a deadline has passed.
+/// A plain read timeout restarts with every byte received, so a client could
+/// keep a connection busy forever by trickling in a byte at a time. The
+/// deadline bounds the total time of a phase such as reading a request head.
+pub(crate) struct TimedStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+}
+
+impl TimedStream {
+    /// Wraps `stream`, failing writes that block longer than `write_timeout`.
+    pub(crate) fn new(stream: TcpStream, write_timeout: Duration) -> io::Result<Self> {
+        stream.set_write_timeout(Some(write_timeout))?;
+        Ok(TimedStream {
+            stream,
+            deadline: None,
+        })
+    }
+}
+
+/// A connection to a client, plain or over TLS, whose reads can be bounded
+/// in time.
+pub(crate) trait ClientStream: Read + Write + Sized {
+    /// Reads from now on must complete within `timeout`.
+    fn set_deadline(&mut self, timeout: Duration);
+
+    /// Reads from now on must each complete within `timeout`, however long
+    /// they take altogether. Used for request bodies, whose transfer time
+    /// depends on their size.
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Another handle to the same connection, e.g. to write a response while
+    /// the request body is being read.
+    fn try_clone(&self) -> io::Result<Self>;
+}
+
+impl ClientStream for TimedStream {
+    fn set_deadline(&mut self, timeout: Duration) {
+        self.deadline = Some(Instant::now() + timeout);
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.deadline = None;
+        self.stream.set_read_timeout(Some(timeout))
+    }
+
+    /// The new handle has no deadline of its own.
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(TimedStream {
+            stream: self.stream.try_clone()?,
+            deadline: None,
+        })
+    }
+}
+
+impl Read for TimedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            self.stream.set_read_timeout(Some(remaining))?;
+        }
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TimedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Whether a read failed because its timeout or deadline passed. Depending
+/// on the platform a socket read timeout shows up as either kind.
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_deadline_spans_reads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            // trickle bytes, each well within the deadline on its own
+            for _ in 0..20 {
+                if stream.write_all(b"x").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut stream = TimedStream::new(stream, Duration::from_secs(1)).unwrap();
+        stream.set_deadline(Duration::from_millis(100));
+        let started = Instant::now();
+        let mut buf = [0; 1];
+        let error = loop {
+            if let Err(e) = stream.read(&mut buf) {
+                break e;
+            }
+        };
+        assert!(is_timeout(&error));
+        assert!(started.elapsed() < Duration::from_millis(300));
+        drop(stream);
+        client.join().unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rustls::{
+    ServerConfig, ServerConnection, StreamOwned,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+
+use crate::{
+    cert,
+    sha::sha256,
+    timeout::{ClientStream, TimedStream},
+};
+
+/// Where to find the certificate to serve HTTPS with, as given on the
+/// command line.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct TlsOptions {
+    /// PEM file of the certificate chain, the server's own certificate first.
+    pub cert: Option<PathBuf>,
+    /// PEM file of the private key of the certificate.
+    pub key: Option<PathBuf>,
+    /// Serve a generated certificate for the names of this machine instead.
+    pub self_signed: bool,
+    /// Port of a plain HTTP listener sending clients to HTTPS.
+    pub redirect_port: Option<u16>,
+    /// Max age of `Strict-Transport-Security`, in seconds.
+    pub hsts: Option<u64>,
+}
+
+/// Settings shared by every TLS connection.
+pub(crate) struct Tls {
+    config: Arc<ServerConfig>,
+    cert: PathBuf,
+    fingerprint: [u8; 32],
+    /// Names a generated certificate is for.
+    generated_for: Option<Vec<String>>,
+}
+
+impl Tls {
+    /// Loads the certificate chain and key, or returns `None` if HTTPS isn't
+    /// enabled.
+    pub(crate) fn load(options: &TlsOptions) -> Result<Option<Tls>, String> {
+        let (cert, key, generated_for) = match (&options.cert, &options.key) {
+            (None, None) if options.self_signed => {
+                let dir = cert::cache_dir()
+                    .ok_or("no directory to cache the certificate in, HOME isn't set")?;
+                let names = cert::local_names();
+                let (cert, key) = cert::cached(&dir, &names)?;
+                (cert, key, Some(names))
+            }
+            (None, None) if options.redirect_port.is_some() || options.hsts.is_some() => {
+                return Err(
+                    "--redirect-http and --hsts need HTTPS, with --tls-cert or --tls-self-signed"
+                        .to_string(),
+                );
+            }
+            (None, None) => return Ok(None),
+            _ if options.self_signed => {
+                return Err("--tls-self-signed can't be combined with --tls-cert".to_string());
+            }
+            (Some(cert), Some(key)) => (cert.clone(), key.clone(), None),
+            _ => return Err("--tls-cert and --tls-key must be given together".to_string()),
+        };
+        let chain = read_chain(&cert)?;
+        let key = PrivateKeyDer::from_pem_file(&key)
+            .map_err(|e| format!("{}: no private key: {e}", key.display()))?;
+        let fingerprint = sha256(&chain[0]);
+        let config = config(chain, key)?;
+        Ok(Some(Tls {
+            config,
+            cert,
+            fingerprint,
+            generated_for,
+        }))
+    }
+
+    /// The file of the certificate chain.
+    pub(crate) fn cert_path(&self) -> &Path {
+        &self.cert
+    }
+
+    /// Names the certificate was generated for, unless it was given.
+    pub(crate) fn generated_for(&self) -> Option<&[String]> {
+        self.generated_for.as_deref()
+    }
+
+    /// SHA-256 digest of the server's certificate, in the usual notation,
+    /// e.g. `4F:0A:...`, for clients to check it against.
+    pub(crate) fn fingerprint(&self) -> String {
+        let hex: Vec<String> = self
+            .fingerprint
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect();
+        hex.join(":")
+    }
+
+    /// Performs the handshake with a client, within the deadline of
+    /// `stream`.
+    pub(crate) fn accept(&self, mut stream: TimedStream) -> io::Result<TlsStream> {
+        let mut session =
+            ServerConnection::new(Arc::clone(&self.config)).map_err(io::Error::other)?;
+        while session.is_handshaking() {
+            session.complete_io(&mut stream)?;
+        }
+        Ok(TlsStream {
+            session: Arc::new(Mutex::new(Session(StreamOwned::new(session, stream)))),
+        })
+    }
+}
+
+fn read_chain(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let chain = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    if chain.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()));
+    }
+    Ok(chain)
+}
+
+fn config(
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<Arc<ServerConfig>, String> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(chain, key))
+        .map_err(|e| e.to_string())?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// A TLS session, which tells the client it's closed when dropped.
+struct Session(StreamOwned<ServerConnection, TimedStream>);
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.conn.send_close_notify();
+        let _ = self.0.flush();
+    }
+}
+
+/// A connection to a client over TLS. Reading and writing go through the
+/// same session, so every handle to the connection shares it.
+pub(crate) struct TlsStream {
+    session: Arc<Mutex<Session>>,
+}
+
+impl ClientStream for TlsStream {
+    fn set_deadline(&mut self, timeout: Duration) {
+        self.session.lock().unwrap().0.sock.set_deadline(timeout);
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.session
+            .lock()
+            .unwrap()
+            .0
+            .sock
+            .set_read_timeout(timeout)
+    }
+
+    /// The new handle shares the deadline of the session.
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(TlsStream {
+            session: Arc::clone(&self.session),
+        })
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.session.lock().unwrap().0.read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.session.lock().unwrap().0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.session.lock().unwrap().0.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, pki_types::ServerName};
+    use std::{
+        fs,
+        net::{TcpListener, TcpStream},
+        process, thread,
+    };
+
+    #[test]
+    fn test_load() {
+        assert!(Tls::load(&TlsOptions::default()).unwrap().is_none());
+        let hsts = TlsOptions {
+            hsts: Some(60),
+            ..TlsOptions::default()
+        };
+        let error = Tls::load(&hsts).err().unwrap();
+        assert!(error.contains("need HTTPS"), "{error}");
+
+        let dir = std::env::temp_dir().join(format!("rup-tls-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.pem");
+        fs::write(&empty, "no certificate here\n").unwrap();
+        let missing = dir.join("missing.pem");
+
+        let only_cert = TlsOptions {
+            cert: Some(empty.clone()),
+            key: None,
+            ..TlsOptions::default()
+        };
+        let error = Tls::load(&only_cert).err().unwrap();
+        assert!(error.contains("--tls-key"), "{error}");
+
+        let options = TlsOptions {
+            cert: Some(empty.clone()),
+            key: Some(empty.clone()),
+            ..TlsOptions::default()
+        };
+        let error = Tls::load(&options).err().unwrap();
+        assert!(error.contains("no certificates found"), "{error}");
+
+        let options = TlsOptions {
+            cert: Some(missing),
+            key: Some(empty),
+            self_signed: true,
+            ..TlsOptions::default()
+        };
+        let error = Tls::load(&options).err().unwrap();
+        assert!(error.contains("--tls-self-signed"), "{error}");
+        let options = TlsOptions {
+            self_signed: false,
+            ..options
+        };
+        assert!(Tls::load(&options).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_handshake() {
+        let dir = std::env::temp_dir().join(format!("rup-handshake-{}", process::id()));
+        let names = ["localhost".to_string(), "127.0.0.1".to_string()];
+        let (cert, key) = cert::cached(&dir, &names).unwrap();
+        let options = TlsOptions {
+            cert: Some(cert.clone()),
+            key: Some(key),
+            ..TlsOptions::default()
+        };
+        let tls = Tls::load(&options).unwrap().unwrap();
+        assert_eq!(tls.fingerprint().len(), 32 * 3 - 1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let stream = TimedStream::new(stream, Duration::from_secs(1)).unwrap();
+                let mut stream = tls.accept(stream).unwrap();
+                let mut buf = [0; 4];
+                stream.read_exact(&mut buf).unwrap();
+                stream.write_all(b"pong").unwrap();
+            }
+        });
+
+        // clients that trust the certificate accept it for both its names
+        let mut roots = RootCertStore::empty();
+        roots.add(read_chain(&cert).unwrap().remove(0)).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let config = Arc::new(config);
+        for name in names {
+            let name = ServerName::try_from(name).unwrap();
+            let session = ClientConnection::new(Arc::clone(&config), name).unwrap();
+            let mut stream = StreamOwned::new(session, TcpStream::connect(addr).unwrap());
+            stream.write_all(b"ping").unwrap();
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"pong");
+        }
+        server.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
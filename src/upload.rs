@@ -0,0 +1,546 @@
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::body::body_error;
+use crate::conditional::{Precondition, Validators, evaluate_write};
+use crate::http::{Exchange, RequestError, Response};
+use crate::multipart::{Multipart, parameter};
+
+/// Longest name an uploaded file is saved under, in bytes, leaving room
+/// for the suffixes of renamed and temporary files.
+const MAX_NAME_LEN: usize = 200;
+/// Numbered names tried for a file whose name is taken, before giving up.
+const MAX_RENAMES: usize = 1000;
+
+/// What to do with a file posted from the upload form whose name is taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ConflictPolicy {
+    /// Save it under a numbered name, e.g. `a (1).txt`.
+    Rename,
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep the existing file and answer 409.
+    Reject,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rename" => Ok(ConflictPolicy::Rename),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "reject" => Ok(ConflictPolicy::Reject),
+            _ => Err("expected one of rename, overwrite, reject".into()),
+        }
+    }
+}
+
+/// Why a body couldn't be stored.
+enum Failure {
+    /// Reading the body failed, which is the client's fault.
+    Body(RequestError),
+    /// Writing the file failed.
+    Disk(io::Error),
+}
+
+/// Stores the request body at `path`, answering 201 if the file is new and
+/// 204 if it replaced one. The body goes to a temporary file next to `path`
+/// first, which is renamed once complete, so readers never see a partial
+/// upload. Missing parent directories are created only if `create_dirs`.
+/// With `If-None-Match: *`, the file is linked into place instead, which
+/// fails rather than replace a file created while the body was received.
+pub(crate) fn put<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    path: &Path,
+    body: &mut R,
+    create_dirs: bool,
+) -> io::Result<usize> {
+    if path.is_dir() {
+        return Response::error(409, "A directory exists at the requested path.")
+            .send_to(stream, exchange);
+    }
+    let validators = fs::metadata(path)
+        .ok()
+        .map(|md| Validators::from_metadata(&md));
+    if evaluate_write(exchange.headers, validators.as_ref()) == Precondition::Failed {
+        return Response::error(412, "The requested path has changed.").send_to(stream, exchange);
+    }
+
+    let parent = path.parent().unwrap();
+    if !parent.is_dir() {
+        if !create_dirs {
+            return Response::error(409, "Parent directory does not exist.")
+                .send_to(stream, exchange);
+        }
+        if let Err(e) = fs::create_dir_all(parent) {
+            let reason = format!("Fail to create parent directory: {e}");
+            return Response::error(409, &reason).send_to(stream, exchange);
+        }
+    }
+
+    let create_only = exchange
+        .headers
+        .get("If-None-Match")
+        .is_some_and(|value| value.trim() == "*");
+    let temp = temp_path(path);
+    let stored = receive(body, &temp).and_then(|()| {
+        let placed = if create_only {
+            fs::hard_link(&temp, path).and_then(|()| fs::remove_file(&temp))
+        } else {
+            fs::rename(&temp, path)
+        };
+        placed.map_err(Failure::Disk)
+    });
+    match stored {
+        Ok(()) => Response::stored(path, validators.is_none()).send_to(stream, exchange),
+        Err(failure) => {
+            let _ = fs::remove_file(&temp);
+            match failure {
+                Failure::Body(e) => Response::error(e.code(), e.reason()).send_to(stream, exchange),
+                Failure::Disk(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    Response::error(412, "The requested path has changed.")
+                        .send_to(stream, exchange)
+                }
+                Failure::Disk(e) => {
+                    let reason = format!("Fail to store file: {e}");
+                    Response::error(500, &reason).send_to(stream, exchange)
+                }
+            }
+        }
+    }
+}
+
+/// Saves every file of a `multipart/form-data` body, as sent by the upload
+/// form of directory listings, into directory `dir`, then redirects the
+/// browser back to the listing at `location`. Each file is stored like with
+/// PUT, under its sanitized name, unless `allowed` rejects the resulting
+/// path. Files are saved in order, so an error about one of them leaves the
+/// files before it in place.
+pub(crate) fn post<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    dir: &Path,
+    location: &str,
+    body: &mut R,
+    policy: ConflictPolicy,
+    allowed: &dyn Fn(&Path) -> bool,
+) -> io::Result<usize> {
+    let content_type = exchange.headers.get("Content-Type").unwrap_or("");
+    let media_type = content_type.split(';').next().unwrap().trim();
+    if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+        return Response::error(415, "Expected multipart/form-data.").send_to(stream, exchange);
+    }
+    let Some(boundary) = parameter(content_type, "boundary").filter(|b| !b.is_empty()) else {
+        return Response::error(400, "Missing multipart boundary").send_to(stream, exchange);
+    };
+
+    let mut multipart = Multipart::new(body, &boundary);
+    loop {
+        let headers = match multipart.next_part() {
+            Ok(Some(headers)) => headers,
+            Ok(None) => break,
+            Err(e) => {
+                let e = body_error(&e);
+                return Response::error(e.code(), e.reason()).send_to(stream, exchange);
+            }
+        };
+        // other form fields, and file inputs left empty, are skipped
+        let Some(filename) = headers
+            .get("Content-Disposition")
+            .and_then(|value| parameter(value, "filename"))
+            .filter(|name| !name.is_empty())
+        else {
+            continue;
+        };
+        let Some(name) = sanitize(&filename) else {
+            let reason = format!("Invalid file name: {filename}");
+            return Response::error(400, &reason).send_to(stream, exchange);
+        };
+        if !allowed(&dir.join(&name)) {
+            let reason = format!("Uploading {name} is not allowed.");
+            return Response::error(403, &reason).send_to(stream, exchange);
+        }
+
+        let temp = temp_path(&dir.join(&name));
+        let saved = receive(&mut multipart, &temp)
+            .and_then(|()| place(&temp, dir, &name, policy).map_err(Failure::Disk));
+        let _ = fs::remove_file(&temp);
+        match saved {
+            Ok(Some(path)) => println!("Saved {}", path.display()),
+            Ok(None) => {
+                let reason = format!("{name} already exists.");
+                return Response::error(409, &reason).send_to(stream, exchange);
+            }
+            Err(Failure::Body(e)) => {
+                return Response::error(e.code(), e.reason()).send_to(stream, exchange);
+            }
+            Err(Failure::Disk(e)) => {
+                let reason = format!("Fail to store {name}: {e}");
+                return Response::error(500, &reason).send_to(stream, exchange);
+            }
+        }
+    }
+    Response::redirect(303, location).send_to(stream, exchange)
+}
+
+/// Moves the complete upload at `temp` to `name` in `dir`, or to another
+/// name if it is taken and the policy says so. Returns where the file ended
+/// up, or `None` if it was rejected. Unless overwriting, the file is linked
+/// rather than renamed, which fails instead of replacing a file that
+/// appeared in the meantime; the caller removes `temp` afterwards.
+fn place(
+    temp: &Path,
+    dir: &Path,
+    name: &str,
+    policy: ConflictPolicy,
+) -> io::Result<Option<PathBuf>> {
+    let path = dir.join(name);
+    match policy {
+        ConflictPolicy::Overwrite if path.is_dir() => Ok(None),
+        ConflictPolicy::Overwrite => fs::rename(temp, &path).map(|()| Some(path)),
+        ConflictPolicy::Reject => match fs::hard_link(temp, &path) {
+            Ok(()) => Ok(Some(path)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(None),
+            Err(e) => Err(e),
+        },
+        ConflictPolicy::Rename => {
+            for n in 0..MAX_RENAMES {
+                let path = match n {
+                    0 => path.clone(),
+                    n => dir.join(numbered(name, n)),
+                };
+                match fs::hard_link(temp, &path) {
+                    Ok(()) => return Ok(Some(path)),
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// `name` with `n` appended to its stem, e.g. `a (2).txt`.
+fn numbered(name: &str, n: usize) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{stem} ({n}).{ext}"),
+        _ => format!("{name} ({n})"),
+    }
+}
+
+/// Makes a file name sent by a browser safe to save in the upload
+/// directory. Only the last path component is kept, characters that are
+/// invalid in file names on common systems are replaced, and leading dots are
+/// removed so that uploads can't become dotfiles. Long names are shortened,
+/// keeping the extension. Returns `None` if nothing is left.
+pub(crate) fn sanitize(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap();
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        return None;
+    }
+    if name.len() <= MAX_NAME_LEN {
+        return Some(name.to_string());
+    }
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if ext.len() <= 16 => (stem, format!(".{ext}")),
+        _ => (name, String::new()),
+    };
+    let mut end = MAX_NAME_LEN - ext.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(format!("{}{ext}", &stem[..end]))
+}
+
+/// Writes the whole body to a new file at `temp` and flushes it to disk.
+fn receive<R: Read>(body: &mut R, temp: &Path) -> Result<(), Failure> {
+    let mut file = File::create_new(temp).map_err(Failure::Disk)?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let len = match body.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Failure::Body(body_error(&e))),
+        };
+        file.write_all(&buf[..len]).map_err(Failure::Disk)?;
+    }
+    file.sync_all().map_err(Failure::Disk)
+}
+
+/// A name next to `path` for an upload in progress, hidden as a dotfile and
+/// unique across concurrent uploads. Only a prefix of a long name is kept,
+/// so that the suffixes don't take the name past the 255 bytes file
+/// systems allow.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = path.file_name().unwrap().to_string_lossy();
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    let prefix = &name[..end];
+    path.with_file_name(format!(".{prefix}.{}-{n}.tmp", process::id()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::headers::Headers;
+    use p_test::p_test;
+
+    fn put_file(path: &Path, fields: &[(&str, &str)], body: &str, create_dirs: bool) -> String {
+        put_body(path, fields, &mut body.as_bytes(), create_dirs)
+    }
+
+    fn put_body(
+        path: &Path,
+        fields: &[(&str, &str)],
+        body: &mut impl Read,
+        create_dirs: bool,
+    ) -> String {
+        let mut headers = Headers::new();
+        for (name, value) in fields {
+            headers.append(name, value);
+        }
+        let exchange = Exchange {
+            headers: &headers,
+            keep_alive: None,
+            head: false,
+            compress: false,
+            chunked: true,
+            hsts: None,
+        };
+        let mut out = vec![];
+        put(&mut out, &exchange, path, body, create_dirs).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        out.lines().next().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_put() {
+        let dir = std::env::temp_dir().join(format!("rup-upload-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+
+        assert_eq!(put_file(&path, &[], "one", false), "HTTP/1.1 201 Created");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one");
+        assert_eq!(
+            put_file(&path, &[], "two", false),
+            "HTTP/1.1 204 No Content"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two");
+
+        let fields = [("If-None-Match", "*")];
+        let status = put_file(&path, &fields, "three", false);
+        assert_eq!(status, "HTTP/1.1 412 Precondition Failed");
+        let fields = [("If-Match", "\"other\"")];
+        let status = put_file(&path, &fields, "three", false);
+        assert_eq!(status, "HTTP/1.1 412 Precondition Failed");
+        let etag = Validators::from_metadata(&fs::metadata(&path).unwrap()).etag;
+        let fields = [("If-Match", etag.as_str())];
+        let status = put_file(&path, &fields, "three", false);
+        assert_eq!(status, "HTTP/1.1 204 No Content");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "three");
+
+        let nested = dir.join("b/c.txt");
+        let status = put_file(&nested, &[], "nested", false);
+        assert_eq!(status, "HTTP/1.1 409 Conflict");
+        let status = put_file(&nested, &[], "nested", true);
+        assert_eq!(status, "HTTP/1.1 201 Created");
+        let status = put_file(&dir.join("b"), &[], "dir", true);
+        assert_eq!(status, "HTTP/1.1 409 Conflict");
+
+        // no temporary files are left behind
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.txt", "b"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A body during whose upload someone else creates the file at `path`.
+    struct Racing<'a> {
+        path: &'a Path,
+        body: &'a [u8],
+    }
+
+    impl Read for Racing<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.path.exists() {
+                fs::write(self.path, "theirs")?;
+            }
+            self.body.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_put_if_none_match_race() {
+        let dir = std::env::temp_dir().join(format!("rup-race-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+
+        let fields = [("If-None-Match", "*")];
+        let mut body = Racing {
+            path: &path,
+            body: b"ours",
+        };
+        let status = put_body(&path, &fields, &mut body, false);
+        assert_eq!(status, "HTTP/1.1 412 Precondition Failed");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theirs");
+
+        let other = dir.join("b.txt");
+        assert_eq!(
+            put_file(&other, &fields, "new", false),
+            "HTTP/1.1 201 Created"
+        );
+        assert_eq!(fs::read_to_string(&other).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[p_test(
+        ("a.txt", Some("a.txt")),
+        ("C:\\Users\\me\\a b.txt", Some("a b.txt")),
+        ("../../etc/passwd", Some("passwd")),
+        (".env", Some("env")),
+        ("a:b*c?.txt", Some("a_b_c_.txt")),
+        ("a\nb", Some("a_b")),
+        (" name. ", Some("name")),
+        ("..", None),
+        ("dir/", None),
+        ("", None),
+    )]
+    fn test_sanitize(name: &str, expected: Option<&str>) {
+        assert_eq!(sanitize(name).as_deref(), expected);
+    }
+
+    #[test]
+    fn test_sanitize_long() {
+        let name = format!("{}.tar.gz", "é".repeat(150));
+        let sanitized = sanitize(&name).unwrap();
+        assert!(sanitized.len() <= MAX_NAME_LEN);
+        assert!(sanitized.starts_with("éé"));
+        assert!(sanitized.ends_with("é.gz"));
+    }
+
+    #[test]
+    fn test_temp_path() {
+        let temp = temp_path(Path::new("/srv/a.txt"));
+        let name = temp.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(".a.txt.") && name.ends_with(".tmp"));
+        assert_eq!(temp.parent(), Some(Path::new("/srv")));
+
+        let long = format!("{}.txt", "é".repeat(125));
+        let temp = temp_path(&Path::new("/srv").join(&long));
+        let name = temp.file_name().unwrap().to_str().unwrap();
+        assert!(name.len() <= 255, "{} bytes", name.len());
+        assert!(name.starts_with(".éé"));
+    }
+
+    #[p_test(
+        ("a.txt", 1, "a (1).txt"),
+        ("a.tar.gz", 2, "a.tar (2).gz"),
+        ("README", 3, "README (3)"),
+    )]
+    fn test_numbered(name: &str, n: usize, expected: &str) {
+        assert_eq!(numbered(name, n), expected);
+    }
+
+    fn post_form(dir: &Path, files: &[(&str, &str)], policy: ConflictPolicy) -> String {
+        let mut body = String::new();
+        for (name, content) in files {
+            body += &format!(
+                "--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\r\n{content}\r\n"
+            );
+        }
+        body += "--b\r\nContent-Disposition: form-data; name=\"other\"\r\n\r\nx\r\n--b--\r\n";
+        let mut headers = Headers::new();
+        headers.append("Content-Type", "multipart/form-data; boundary=b");
+        let exchange = Exchange {
+            headers: &headers,
+            keep_alive: None,
+            head: false,
+            compress: false,
+            chunked: true,
+            hsts: None,
+        };
+        let allowed = |path: &Path| path.extension().is_none_or(|ext| ext != "key");
+        let mut out = vec![];
+        post(
+            &mut out,
+            &exchange,
+            dir,
+            "/up/",
+            &mut body.as_bytes(),
+            policy,
+            &allowed,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        out.lines().next().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_post() {
+        let dir = std::env::temp_dir().join(format!("rup-post-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+
+        let files = [("a.txt", "one"), ("b.txt", "two")];
+        let status = post_form(&dir, &files, ConflictPolicy::Rename);
+        assert_eq!(status, "HTTP/1.1 303 See Other");
+        assert_eq!((read("a.txt"), read("b.txt")), ("one".into(), "two".into()));
+
+        let status = post_form(&dir, &[("a.txt", "three")], ConflictPolicy::Rename);
+        assert_eq!(status, "HTTP/1.1 303 See Other");
+        assert_eq!(
+            (read("a.txt"), read("a (1).txt")),
+            ("one".into(), "three".into())
+        );
+
+        let status = post_form(&dir, &[("a.txt", "four")], ConflictPolicy::Reject);
+        assert_eq!(status, "HTTP/1.1 409 Conflict");
+        assert_eq!(read("a.txt"), "one");
+
+        let status = post_form(&dir, &[("a.txt", "five")], ConflictPolicy::Overwrite);
+        assert_eq!(status, "HTTP/1.1 303 See Other");
+        assert_eq!(read("a.txt"), "five");
+
+        let status = post_form(&dir, &[("my.key", "secret")], ConflictPolicy::Rename);
+        assert_eq!(status, "HTTP/1.1 403 Forbidden");
+        let status = post_form(&dir, &[("..", "x")], ConflictPolicy::Rename);
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a (1).txt", "a.txt", "b.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use crate::glob::glob_match;
+use crate::resolve::real_path;
+
+/// Decides which paths under the root are served and listed.
+/// Deny rules win over allow rules, and paths matched by neither are visible
+/// unless one of their segments is a dotfile.
+///
+/// A rule without `/`, apart from a trailing one, is matched against every
+/// segment of a path, so `.git/` hides the `.git` directory wherever it is,
+/// and `*.key` hides all keys. Any other rule is matched against the path
+/// relative to the root and each of its parent directories, e.g. `/build`
+/// or `docs/*.md`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Visibility {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl Visibility {
+    /// Whether `path`, relative to the root, may be served and listed.
+    /// Segments that aren't UTF-8 are matched lossily, so that e.g. a
+    /// dotfile with such a name is still hidden.
+    pub(crate) fn is_visible(&self, path: &Path) -> bool {
+        let segments: Vec<_> = path.iter().map(|s| s.to_string_lossy()).collect();
+        let segments: Vec<&str> = segments
+            .iter()
+            .map(|s| s.as_ref())
+            .filter(|s| *s != "." && !s.is_empty())
+            .collect();
+        if self.deny.iter().any(|rule| rule_matches(rule, &segments)) {
+            false
+        } else if self.allow.iter().any(|rule| rule_matches(rule, &segments)) {
+            true
+        } else {
+            !segments.iter().any(|s| s.starts_with('.'))
+        }
+    }
+
+    /// Whether the file at `path` under `root` may be served and listed,
+    /// both as it is reached and as it is found once symlinks are followed,
+    /// so that e.g. a link `public -> .git` doesn't reveal `.git`.
+    pub(crate) fn allows(&self, root: &Path, path: &Path) -> bool {
+        path.strip_prefix(root)
+            .is_ok_and(|relative| self.is_visible(relative))
+            && real_path(root, path).is_none_or(|real| self.is_visible(&real))
+    }
+}
+
+pub(crate) fn rule_matches(rule: &str, segments: &[&str]) -> bool {
+    let rule = rule.strip_suffix('/').unwrap_or(rule);
+    if rule.contains('/') {
+        let rule = rule.trim_start_matches('/');
+        (1..=segments.len()).any(|n| glob_match(rule, &segments[..n].join("/")))
+    } else {
+        segments.iter().any(|segment| glob_match(rule, segment))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    fn visibility() -> Visibility {
+        Visibility {
+            allow: vec![".well-known/".to_string(), "public/.*".to_string()],
+            deny: vec![
+                ".git/".to_string(),
+                "*.key".to_string(),
+                "/private/".to_string(),
+            ],
+        }
+    }
+
+    #[p_test(
+        ("index.html", true),
+        ("css/site.css", true),
+        ("", true),
+        (".env", false),
+        ("app/.env", false),
+        (".git/config", false),
+        (".git", false),
+        (".well-known", true),
+        (".well-known/security.txt", true),
+        ("server.key", false),
+        ("certs/server.key", false),
+        ("private", false),
+        ("private/notes.txt", false),
+        ("docs/private/notes.txt", true),
+        ("public/.htaccess", true),
+        ("public/a/.htaccess", false),
+    )]
+    fn test_visibility(path: &str, expected: bool) {
+        assert_eq!(visibility().is_visible(Path::new(path)), expected);
+    }
+
+    #[test]
+    fn test_default() {
+        let visibility = Visibility::default();
+        assert!(visibility.is_visible(Path::new("./a/b.txt")));
+        assert!(!visibility.is_visible(Path::new("./a/.b.txt")));
+        assert!(!visibility.is_visible(Path::new(".hidden/b.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let visibility = Visibility {
+            allow: vec![],
+            deny: vec!["*.key".to_string()],
+        };
+        let dotfile = Path::new(OsStr::from_bytes(b".secret\xff"));
+        assert!(!visibility.is_visible(dotfile));
+        assert!(!visibility.is_visible(&dotfile.join("config")));
+        assert!(!visibility.is_visible(Path::new(OsStr::from_bytes(b"a\xff.key"))));
+        assert!(visibility.is_visible(Path::new(OsStr::from_bytes(b"a\xff.txt"))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked() {
+        let root = std::env::temp_dir().join(format!("rup-visibility-{}", std::process::id()));
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".git/config"), "").unwrap();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::os::unix::fs::symlink(root.join(".git"), root.join("public")).unwrap();
+
+        let visibility = Visibility::default();
+        assert!(visibility.allows(&root, &root.join("a.txt")));
+        assert!(!visibility.allows(&root, &root.join(".git/config")));
+        assert!(!visibility.allows(&root, &root.join("public")));
+        assert!(!visibility.allows(&root, &root.join("public/config")));
+        assert!(!visibility.allows(&root, &root.join("public/new.txt")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_deny_wins() {
+        let visibility = Visibility {
+            allow: vec![".*".to_string()],
+            deny: vec![".env".to_string()],
+        };
+        assert!(visibility.is_visible(Path::new(".htaccess")));
+        assert!(!visibility.is_visible(Path::new(".env")));
+    }
+}
@@ -0,0 +1,400 @@
+use std::fs::{self, Metadata};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::body::body_error;
+use crate::conditional::Validators;
+use crate::date::http_date;
+use crate::decode::encode_path;
+use crate::http::{Exchange, RequestError, Response, content_type, mime_type};
+use crate::xml::{self, DAV, Element, escape};
+
+/// Largest PROPFIND or PROPPATCH body accepted.
+const MAX_XML_BODY: u64 = 64 * 1024;
+
+/// Live properties of files and directories, all in the `DAV:` namespace.
+const PROPERTIES: [&str; 6] = [
+    "displayname",
+    "resourcetype",
+    "getcontentlength",
+    "getcontenttype",
+    "getlastmodified",
+    "getetag",
+];
+
+/// What a PROPFIND request asks for.
+#[derive(Debug, PartialEq)]
+enum Find {
+    /// Values of all properties.
+    All,
+    /// Names of all properties.
+    Names,
+    /// Values of the properties given by namespace and name.
+    Props(Vec<(String, String)>),
+}
+
+/// Answers PROPFIND with the properties of `path`, and of its entries with
+/// `Depth: 1` if it is a directory. Entries for which `visible` is false
+/// are left out, as in listings. `Depth: infinity`, which is also what no
+/// `Depth` means, is refused as RFC 4918 allows, since it could walk the
+/// whole root.
+pub(crate) fn propfind<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    root: &Path,
+    path: &Path,
+    body: &mut R,
+    visible: &dyn Fn(&Path) -> bool,
+) -> io::Result<usize> {
+    let with_entries = match exchange.headers.get("Depth").map(str::trim) {
+        Some("0") => false,
+        Some("1") => true,
+        None => return finite_depth_only(stream, exchange),
+        Some(depth) if depth.eq_ignore_ascii_case("infinity") => {
+            return finite_depth_only(stream, exchange);
+        }
+        Some(_) => return Response::error(400, "Invalid Depth").send_to(stream, exchange),
+    };
+    let find = match read_xml(body).and_then(|doc| parse_propfind(doc.as_ref())) {
+        Ok(find) => find,
+        Err(e) => return Response::error(e.code(), e.reason()).send_to(stream, exchange),
+    };
+
+    let mut doc = multistatus_start();
+    doc += &describe(root, path, &fs::metadata(path)?, &find);
+    if with_entries && path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|entry| visible(entry))
+            .collect::<Vec<_>>();
+        entries.sort();
+        for entry in entries {
+            // e.g. broken symlinks
+            if let Ok(md) = fs::metadata(&entry) {
+                doc += &describe(root, &entry, &md, &find);
+            }
+        }
+    }
+    doc += "</D:multistatus>\n";
+    Response::multi_status(&doc).send_to(stream, exchange)
+}
+
+/// Refuses a PROPFIND of infinite depth, naming the precondition that
+/// failed so that clients can retry with `Depth: 1`.
+fn finite_depth_only<W: Write>(stream: &mut W, exchange: &Exchange) -> io::Result<usize> {
+    let doc = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+        <D:error xmlns:D=\"DAV:\"><D:propfind-finite-depth/></D:error>\n";
+    Response::dav_error(403, doc).send_to(stream, exchange)
+}
+
+/// Answers PROPPATCH as if every property had been set or removed. Dead
+/// properties aren't stored, but clients such as file managers set some
+/// after uploading and give up on errors.
+pub(crate) fn proppatch<W: Write, R: Read>(
+    stream: &mut W,
+    exchange: &Exchange,
+    root: &Path,
+    path: &Path,
+    body: &mut R,
+) -> io::Result<usize> {
+    let names = match read_xml(body).and_then(|doc| parse_propertyupdate(doc.as_ref())) {
+        Ok(names) => names,
+        Err(e) => return Response::error(e.code(), e.reason()).send_to(stream, exchange),
+    };
+    let props = names
+        .iter()
+        .map(|(namespace, name)| empty_element(namespace, name))
+        .collect::<String>();
+    let mut doc = multistatus_start();
+    doc += &format!(
+        "<D:response><D:href>{}</D:href>{}</D:response>\n",
+        href(root, path, path.is_dir()),
+        propstat(&props, "200 OK")
+    );
+    doc += "</D:multistatus>\n";
+    Response::multi_status(&doc).send_to(stream, exchange)
+}
+
+/// Reads an XML request body, which may be empty.
+fn read_xml<R: Read>(body: &mut R) -> Result<Option<Element>, RequestError> {
+    let mut buf = vec![];
+    body.take(MAX_XML_BODY + 1)
+        .read_to_end(&mut buf)
+        .map_err(|e| body_error(&e))?;
+    if buf.len() as u64 > MAX_XML_BODY {
+        return Err(RequestError::PayloadTooLarge);
+    }
+    if buf.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    let doc = String::from_utf8(buf).map_err(|_| "XML body is not UTF-8".to_string())?;
+    let doc = doc.trim_start_matches('\u{feff}');
+    match xml::parse(doc) {
+        Ok(root) => Ok(Some(root)),
+        Err(e) => Err(format!("Invalid XML body: {e}").into()),
+    }
+}
+
+/// An empty PROPFIND body asks for all properties.
+fn parse_propfind(doc: Option<&Element>) -> Result<Find, RequestError> {
+    let Some(root) = doc else {
+        return Ok(Find::All);
+    };
+    if !root.is(DAV, "propfind") {
+        return Err("Expected a propfind element".to_string().into());
+    }
+    if root.child("allprop").is_some() {
+        Ok(Find::All)
+    } else if root.child("propname").is_some() {
+        Ok(Find::Names)
+    } else if let Some(prop) = root.child("prop") {
+        Ok(Find::Props(names_in(prop)))
+    } else {
+        Err("Expected allprop, propname or prop".to_string().into())
+    }
+}
+
+/// Names of the properties set or removed by a PROPPATCH body.
+fn parse_propertyupdate(doc: Option<&Element>) -> Result<Vec<(String, String)>, RequestError> {
+    let Some(root) = doc.filter(|root| root.is(DAV, "propertyupdate")) else {
+        return Err("Expected a propertyupdate element".to_string().into());
+    };
+    let names = root
+        .children
+        .iter()
+        .filter(|update| update.is(DAV, "set") || update.is(DAV, "remove"))
+        .filter_map(|update| update.child("prop"))
+        .flat_map(names_in)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return Err("No properties to update".to_string().into());
+    }
+    Ok(names)
+}
+
+fn names_in(prop: &Element) -> Vec<(String, String)> {
+    prop.children
+        .iter()
+        .map(|p| (p.namespace.clone(), p.name.clone()))
+        .collect()
+}
+
+fn multistatus_start() -> String {
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n".to_string()
+}
+
+/// The `response` element describing one file or directory.
+fn describe(root: &Path, path: &Path, md: &Metadata, find: &Find) -> String {
+    let defined = || {
+        PROPERTIES
+            .iter()
+            .filter_map(|name| property(name, path, md).map(|value| (*name, value)))
+    };
+    let propstats = match find {
+        Find::All => {
+            let props = defined()
+                .map(|(name, value)| format!("<D:{name}>{value}</D:{name}>"))
+                .collect::<String>();
+            propstat(&props, "200 OK")
+        }
+        Find::Names => {
+            let props = defined()
+                .map(|(name, _)| format!("<D:{name}/>"))
+                .collect::<String>();
+            propstat(&props, "200 OK")
+        }
+        Find::Props(names) => {
+            let mut found = String::new();
+            let mut missing = String::new();
+            for (namespace, name) in names {
+                match property(name, path, md).filter(|_| namespace == DAV) {
+                    Some(value) => found += &format!("<D:{name}>{value}</D:{name}>"),
+                    None => missing += &empty_element(namespace, name),
+                }
+            }
+            let mut propstats = String::new();
+            if !found.is_empty() {
+                propstats += &propstat(&found, "200 OK");
+            }
+            if !missing.is_empty() {
+                propstats += &propstat(&missing, "404 Not Found");
+            }
+            propstats
+        }
+    };
+    format!(
+        "<D:response><D:href>{}</D:href>{propstats}</D:response>\n",
+        href(root, path, md.is_dir())
+    )
+}
+
+fn propstat(props: &str, status: &str) -> String {
+    format!(
+        "<D:propstat><D:prop>{props}</D:prop><D:status>HTTP/1.1 {status}</D:status></D:propstat>"
+    )
+}
+
+/// Value of the live property `name` of a file or directory, already
+/// escaped, or `None` if it doesn't have it.
+fn property(name: &str, path: &Path, md: &Metadata) -> Option<String> {
+    match name {
+        "displayname" => path.file_name().map(|n| escape(&n.to_string_lossy())),
+        "resourcetype" if md.is_dir() => Some("<D:collection/>".into()),
+        "resourcetype" => Some(String::new()),
+        "getcontentlength" if md.is_file() => Some(md.len().to_string()),
+        "getcontenttype" if md.is_file() => Some(escape(&content_type(mime_type(path)))),
+        "getlastmodified" => md.modified().ok().map(http_date),
+        "getetag" if md.is_file() => Some(escape(&Validators::from_metadata(md).etag)),
+        _ => None,
+    }
+}
+
+/// An empty element for a property, declaring its namespace.
+fn empty_element(namespace: &str, name: &str) -> String {
+    if namespace == DAV {
+        format!("<D:{name}/>")
+    } else {
+        format!("<{name} xmlns=\"{}\"/>", escape(namespace))
+    }
+}
+
+/// URL path of `path` under the root. Directories end with a slash.
+fn href(root: &Path, path: &Path, is_dir: bool) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut href = String::new();
+    for component in relative.components() {
+        href.push('/');
+        href.push_str(&component.as_os_str().to_string_lossy());
+    }
+    if is_dir || href.is_empty() {
+        href.push('/');
+    }
+    escape(&encode_path(&href))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::headers::Headers;
+    use p_test::p_test;
+    use std::process;
+
+    fn find(doc: &str) -> Result<Find, u16> {
+        let root = xml::parse(doc).map_err(|_| 0u16)?;
+        parse_propfind(Some(&root)).map_err(|e| e.code())
+    }
+
+    #[test]
+    fn test_parse_propfind() {
+        assert_eq!(parse_propfind(None).unwrap(), Find::All);
+        let doc = r#"<propfind xmlns="DAV:"><allprop/></propfind>"#;
+        assert_eq!(find(doc).unwrap(), Find::All);
+        let doc = r#"<D:propfind xmlns:D="DAV:"><D:propname/></D:propfind>"#;
+        assert_eq!(find(doc).unwrap(), Find::Names);
+        let doc = r#"<D:propfind xmlns:D="DAV:"><D:prop><D:getetag/><x:a xmlns:x="urn:x"/></D:prop></D:propfind>"#;
+        let expected = vec![
+            ("DAV:".to_string(), "getetag".to_string()),
+            ("urn:x".to_string(), "a".to_string()),
+        ];
+        assert_eq!(find(doc).unwrap(), Find::Props(expected));
+        assert_eq!(
+            find(r#"<propfind xmlns="urn:x"><allprop/></propfind>"#),
+            Err(400)
+        );
+        assert_eq!(find(r#"<propfind xmlns="DAV:"/>"#), Err(400));
+    }
+
+    #[test]
+    fn test_parse_propertyupdate() {
+        let doc = r#"<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:z">
+            <D:set><D:prop><Z:author>Me</Z:author></D:prop></D:set>
+            <D:remove><D:prop><Z:copyright/></D:prop></D:remove>
+            </D:propertyupdate>"#;
+        let root = xml::parse(doc).unwrap();
+        let names = parse_propertyupdate(Some(&root)).unwrap();
+        let expected = [("urn:z", "author"), ("urn:z", "copyright")];
+        let expected = expected.map(|(ns, name)| (ns.to_string(), name.to_string()));
+        assert_eq!(names, expected);
+        assert!(parse_propertyupdate(None).is_err());
+    }
+
+    #[p_test(
+        ("", false, "/"),
+        ("a b.txt", false, "/a%20b.txt"),
+        ("sub/dir", true, "/sub/dir/"),
+        ("x&y", false, "/x&amp;y"),
+    )]
+    fn test_href(relative: &str, is_dir: bool, expected: &str) {
+        let root = Path::new("/srv/www");
+        assert_eq!(href(root, &root.join(relative), is_dir), expected);
+    }
+
+    fn propfind_doc(root: &Path, path: &Path, depth: Option<&str>, body: &str) -> String {
+        let mut headers = Headers::new();
+        if let Some(depth) = depth {
+            headers.append("Depth", depth);
+        }
+        let exchange = Exchange {
+            headers: &headers,
+            keep_alive: None,
+            head: false,
+            compress: false,
+            chunked: true,
+            hsts: None,
+        };
+        let visible = |path: &Path| !path.ends_with("hidden");
+        let mut out = vec![];
+        propfind(
+            &mut out,
+            &exchange,
+            root,
+            path,
+            &mut body.as_bytes(),
+            &visible,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_propfind() {
+        let root = std::env::temp_dir().join(format!("rup-propfind-{}", process::id()));
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::create_dir_all(root.join("hidden")).unwrap();
+        fs::write(root.join("dir/a.txt"), "hello").unwrap();
+
+        let out = propfind_doc(&root, &root.join("dir"), Some("1"), "");
+        assert!(out.starts_with("HTTP/1.1 207 Multi-Status\r\n"));
+        assert!(out.contains("<D:href>/dir/</D:href>"));
+        assert!(out.contains("<D:resourcetype><D:collection/></D:resourcetype>"));
+        assert!(out.contains("<D:href>/dir/a.txt</D:href>"));
+        assert!(out.contains("<D:getcontentlength>5</D:getcontentlength>"));
+        assert!(out.contains("<D:getcontenttype>text/plain; charset=utf-8</D:getcontenttype>"));
+
+        let out = propfind_doc(&root, &root, Some("1"), "");
+        assert!(out.contains("<D:href>/</D:href>"));
+        assert!(out.contains("<D:href>/dir/</D:href>"));
+        assert!(!out.contains("hidden"));
+
+        let body = r#"<propfind xmlns="DAV:"><prop><getcontentlength/><foo xmlns="urn:x"/></prop></propfind>"#;
+        let out = propfind_doc(&root, &root.join("dir/a.txt"), Some("0"), body);
+        let found = "<D:propstat><D:prop><D:getcontentlength>5</D:getcontentlength></D:prop>\
+            <D:status>HTTP/1.1 200 OK</D:status></D:propstat>";
+        let missing = "<D:propstat><D:prop><foo xmlns=\"urn:x\"/></D:prop>\
+            <D:status>HTTP/1.1 404 Not Found</D:status></D:propstat>";
+        assert!(out.contains(&format!("{found}{missing}")));
+
+        for depth in [Some("infinity"), None] {
+            let out = propfind_doc(&root, &root, depth, "");
+            assert!(out.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+            assert!(out.contains("Content-Type: application/xml; charset=utf-8\r\n"));
+            assert!(out.contains("<D:error xmlns:D=\"DAV:\"><D:propfind-finite-depth/></D:error>"));
+        }
+        let out = propfind_doc(&root, &root, Some("2"), "");
+        assert!(out.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        let out = propfind_doc(&root, &root, Some("0"), "<propfind");
+        assert!(out.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+/// Namespace of the elements defined by WebDAV.
+pub(crate) const DAV: &str = "DAV:";
+/// Deepest nesting of elements accepted.
+const MAX_DEPTH: usize = 32;
+
+/// An element of an XML document, with its namespace resolved. Text and
+/// attributes other than namespace declarations are dropped, as the WebDAV
+/// requests the server reads only convey information in element names.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Element {
+    pub namespace: String,
+    pub name: String,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    pub(crate) fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace == namespace && self.name == name
+    }
+
+    /// The first child named `name` in the `DAV:` namespace.
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.is(DAV, name))
+    }
+}
+
+/// Parses a document into its root element. Document type declarations are
+/// rejected rather than processed, so entities can't expand.
+pub(crate) fn parse(doc: &str) -> Result<Element, String> {
+    let mut parser = Parser { rest: doc };
+    parser.skip_misc()?;
+    let root = parser.element(&[], 0)?;
+    parser.skip_misc()?;
+    if !parser.rest.is_empty() {
+        return Err("Content after the root element".into());
+    }
+    Ok(root)
+}
+
+/// Escapes text for use in element content or attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+/// Namespace prefixes in scope, innermost last. The default namespace has an
+/// empty prefix.
+type Scope<'a> = [(&'a str, String)];
+
+impl<'a> Parser<'a> {
+    /// Skips whitespace, comments and processing instructions such as the
+    /// XML declaration.
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            self.rest = self.rest.trim_start();
+            if self.rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest.starts_with("<!") {
+                return Err("Document type declarations are not supported".into());
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), String> {
+        match self.rest.find(end) {
+            Some(i) => {
+                self.rest = &self.rest[i + end.len()..];
+                Ok(())
+            }
+            None => Err(format!("Missing {end}")),
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, String> {
+        let end = self
+            .rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '='))
+            .unwrap_or(self.rest.len());
+        let (name, rest) = self.rest.split_at(end);
+        if name.is_empty() || name.contains(['<', '"', '\'', '&']) {
+            return Err("Invalid name".into());
+        }
+        self.rest = rest;
+        Ok(name)
+    }
+
+    /// Parses an element starting at `<`, with the prefixes of its parent.
+    fn element(&mut self, scope: &Scope<'a>, depth: usize) -> Result<Element, String> {
+        if depth > MAX_DEPTH {
+            return Err("Elements are nested too deeply".into());
+        }
+        self.rest = self.rest.strip_prefix('<').ok_or("Expected an element")?;
+        let qname = self.name()?;
+
+        let mut scope = scope.to_vec();
+        let empty = loop {
+            self.rest = self.rest.trim_start();
+            if let Some(rest) = self.rest.strip_prefix("/>") {
+                self.rest = rest;
+                break true;
+            }
+            if let Some(rest) = self.rest.strip_prefix('>') {
+                self.rest = rest;
+                break false;
+            }
+            let attribute = self.name()?;
+            self.rest = self
+                .rest
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or("Expected = after an attribute name")?
+                .trim_start();
+            let quote = self.rest.chars().next().filter(|c| matches!(c, '"' | '\''));
+            let quote = quote.ok_or("Expected a quoted attribute value")?;
+            let end = self.rest[1..]
+                .find(quote)
+                .ok_or("Unterminated attribute value")?;
+            let value = unescape(&self.rest[1..end + 1]);
+            self.rest = &self.rest[end + 2..];
+            if attribute == "xmlns" {
+                scope.push(("", value));
+            } else if let Some(prefix) = attribute.strip_prefix("xmlns:") {
+                scope.push((prefix, value));
+            }
+        };
+
+        let (prefix, name) = qname.split_once(':').unwrap_or(("", qname));
+        let namespace = match scope.iter().rev().find(|(p, _)| *p == prefix) {
+            Some((_, namespace)) => namespace.clone(),
+            None if prefix.is_empty() => String::new(),
+            None => return Err(format!("Undeclared namespace prefix: {prefix}")),
+        };
+        let mut element = Element {
+            namespace,
+            name: name.to_string(),
+            children: vec![],
+        };
+        if empty {
+            return Ok(element);
+        }
+
+        loop {
+            // text is skipped
+            let i = self.rest.find('<').ok_or("Unterminated element")?;
+            self.rest = &self.rest[i..];
+            if let Some(rest) = self.rest.strip_prefix("</") {
+                self.rest = rest;
+                if self.name()? != qname {
+                    return Err(format!("Mismatched end tag for {qname}"));
+                }
+                self.rest = self
+                    .rest
+                    .trim_start()
+                    .strip_prefix('>')
+                    .ok_or("Expected > after an end tag")?;
+                return Ok(element);
+            } else if self.rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest.starts_with("<![CDATA[") {
+                self.skip_past("]]>")?;
+            } else if self.rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else {
+                element.children.push(self.element(&scope, depth + 1)?);
+            }
+        }
+    }
+}
+
+/// Replaces the predefined entities and character references, which is all
+/// namespace names may contain.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = value;
+    while let Some(i) = rest.find('&') {
+        unescaped.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let c = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            reference => reference
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| reference.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use p_test::p_test;
+
+    fn names(element: &Element) -> Vec<String> {
+        let mut all = vec![format!("{{{}}}{}", element.namespace, element.name)];
+        for child in &element.children {
+            all.extend(names(child));
+        }
+        all
+    }
+
+    #[test]
+    fn test_parse() {
+        let doc = r#"<?xml version="1.0" encoding="utf-8" ?>
+            <!-- a comment -->
+            <D:propfind xmlns:D="DAV:">
+              <D:prop xmlns:x='urn:x&amp;y'>
+                <D:getetag/>
+                <x:color>red &amp; <![CDATA[<blue>]]></x:color>
+                <size xmlns="urn:z" unit="px"></size>
+              </D:prop>
+            </D:propfind>"#;
+        let root = parse(doc).unwrap();
+        assert!(root.is(DAV, "propfind"));
+        assert!(root.child("prop").is_some());
+        assert_eq!(
+            names(&root),
+            [
+                "{DAV:}propfind",
+                "{DAV:}prop",
+                "{DAV:}getetag",
+                "{urn:x&y}color",
+                "{urn:z}size",
+            ]
+        );
+    }
+
+    #[p_test(
+        ("<a><b></a>"),
+        ("<a>"),
+        ("<x:a/>"),
+        ("<a/><b/>"),
+        ("<!DOCTYPE a [<!ENTITY e \"e\">]><a/>"),
+        ("<a b=c/>"),
+        ("<a\"b/>"),
+        ("text"),
+        (""),
+    )]
+    fn test_invalid(doc: &str) {
+        assert!(parse(doc).is_err());
+    }
+
+    #[test]
+    fn test_depth() {
+        let doc = format!("{}{}", "<a>".repeat(40), "</a>".repeat(40));
+        assert!(parse(&doc).is_err());
+        let doc = format!("{}{}", "<a>".repeat(20), "</a>".repeat(20));
+        assert!(parse(&doc).is_ok());
+    }
+
+    #[p_test(
+        ("a&amp;b", "a&b"),
+        ("&#65;&#x42;", "AB"),
+        ("&bogus; &", "&bogus; &"),
+    )]
+    fn test_unescape(value: &str, expected: &str) {
+        assert_eq!(unescape(value), expected);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;&lt;/a&gt;"
+        );
+    }
+}